@@ -0,0 +1,369 @@
+use crate::{
+  format::{Container, FormatPreference, MediaKind, Quality, RecordedFormat},
+  library::{media_dir, media_path, write_json},
+  query::Query,
+  youtube::{PlaylistInfo, PlaylistVideo, PlaylistVideos, YouTubeChannel},
+};
+use serde::{Deserialize, Serialize};
+use std::{
+  collections::{HashMap, HashSet},
+  path::{Path, PathBuf},
+  time::{Duration, Instant},
+};
+use tokio::task::JoinHandle;
+
+/// How long after a change the catalog is saved, so changes made together are saved at once.
+pub const SAVE_DELAY: Duration = Duration::from_secs(2);
+
+fn catalog_path(root: &Path) -> PathBuf {
+  root.join("catalog.json")
+}
+
+/// Older versions created an empty file before downloading into it, and left it behind when
+/// the download failed.
+fn is_downloaded(path: &Path) -> bool {
+  std::fs::metadata(path).is_ok_and(|metadata| metadata.is_file() && metadata.len() > 0)
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct CatalogPlaylist {
+  pub title: String,
+  channel_id: String,
+  pub video_ids: Vec<String>,
+  total_count: Option<u32>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct LibraryFile {
+  pub format: RecordedFormat,
+  pub size: u64,
+}
+
+/// What is kept in `catalog.json`.
+#[derive(Clone, Default, Serialize, Deserialize)]
+struct Contents {
+  channels: HashMap<String, YouTubeChannel>,
+  playlists: HashMap<String, CatalogPlaylist>,
+  videos: HashMap<String, PlaylistVideo>,
+  files: HashMap<MediaKind, HashMap<String, LibraryFile>>,
+  /// Videos that have been opened in the player.
+  watched: HashSet<String>,
+}
+
+/// Everything fetched from YouTube and downloaded into the library, so playlists reopen
+/// without waiting on the API and the library can be browsed offline.
+///
+/// Lives in the library directory. Changes are written by `save`, or shortly after they are
+/// made by `save_in_background`.
+#[derive(Default)]
+pub struct Catalog {
+  library_dir: PathBuf,
+  /// Goes up with every change, so what is derived from the catalog knows to be rebuilt.
+  revision: u64,
+  contents: Contents,
+  /// When the oldest change that hasn't been written yet was made.
+  changed_at: Option<Instant>,
+  /// The catalog file couldn't be read, so it is left as it is rather than overwritten.
+  read_only: bool,
+  write: Option<JoinHandle<()>>,
+}
+
+impl Catalog {
+  /// Also returns why the catalog file couldn't be read, if it couldn't; the catalog is then
+  /// empty and isn't saved.
+  pub fn load(library_dir: PathBuf) -> (Self, Option<String>) {
+    let path = catalog_path(&library_dir);
+
+    let error = match std::fs::read(&path) {
+      Ok(bytes) => match serde_json::from_slice(&bytes) {
+        Ok(contents) => {
+          let catalog = Self {
+            library_dir,
+            contents,
+            ..Default::default()
+          };
+
+          return (catalog, None);
+        }
+        Err(error) => format!("{} is invalid: {error}", path.display()),
+      },
+      Err(error) if error.kind() == std::io::ErrorKind::NotFound => {
+        let mut catalog = Self::import_files(library_dir);
+        catalog.changed();
+        catalog.save();
+
+        return (catalog, None);
+      }
+      Err(error) => format!("{} can't be read: {error}", path.display()),
+    };
+
+    let catalog = Self {
+      library_dir,
+      read_only: true,
+      ..Default::default()
+    };
+
+    (catalog, Some(error))
+  }
+
+  /// Builds a catalog for a library from before there was one, out of its `{id}.mp4` files.
+  fn import_files(library_dir: PathBuf) -> Self {
+    let mut catalog = Self {
+      library_dir,
+      ..Default::default()
+    };
+
+    let Ok(entries) = std::fs::read_dir(media_dir(&catalog.library_dir, MediaKind::Video)) else {
+      return catalog;
+    };
+
+    for entry in entries.flatten() {
+      let path = entry.path();
+
+      let Some(id) = path
+        .file_stem()
+        .and_then(|id| id.to_str())
+        .filter(|_| path.extension().is_some_and(|extension| extension == "mp4"))
+        .filter(|_| is_downloaded(&path))
+        .map(str::to_string)
+      else {
+        continue;
+      };
+
+      // downloads were always the lowest quality mp4 before there was a catalog
+      let format = RecordedFormat {
+        preference: FormatPreference {
+          kind: MediaKind::Video,
+          quality: Quality::Lowest,
+          container: Container::Mp4,
+        },
+        itag: 0,
+        container: "mp4".into(),
+        quality_label: None,
+      };
+
+      catalog.insert_file(&id, &path, format);
+    }
+
+    catalog
+  }
+
+  fn changed(&mut self) {
+    self.revision += 1;
+    self.changed_at.get_or_insert_with(Instant::now);
+  }
+
+  pub fn has_unsaved_changes(&self) -> bool {
+    self.changed_at.is_some() && !self.read_only
+  }
+
+  /// Writes the changes right away, after any write already under way.
+  pub fn save(&mut self) {
+    if let Some(write) = self.write.take() {
+      while !write.is_finished() {
+        std::thread::sleep(Duration::from_millis(10));
+      }
+    }
+
+    if self.has_unsaved_changes() {
+      self.changed_at = None;
+      write_json(catalog_path(&self.library_dir), &self.contents);
+    }
+  }
+
+  /// The write under way, if there is one, for whatever has to wait on the catalog file.
+  pub fn take_write(&mut self) -> Option<JoinHandle<()>> {
+    self.write.take()
+  }
+
+  /// Writes the changes off the UI thread once they are a moment old.
+  pub fn save_in_background(&mut self) {
+    if !self.has_unsaved_changes()
+      || self
+        .changed_at
+        .is_some_and(|changed_at| changed_at.elapsed() < SAVE_DELAY)
+      || self
+        .write
+        .as_ref()
+        .is_some_and(|write| !write.is_finished())
+    {
+      return;
+    }
+
+    self.changed_at = None;
+    let path = catalog_path(&self.library_dir);
+    let contents = self.contents.clone();
+
+    self.write = Some(tokio::task::spawn_blocking(move || {
+      write_json(path, &contents)
+    }));
+  }
+
+  pub fn revision(&self) -> u64 {
+    self.revision
+  }
+
+  /// Points the catalog at a library that has already been moved to `library_dir`.
+  pub fn set_library_dir(&mut self, library_dir: PathBuf) {
+    self.library_dir = library_dir;
+    self.changed();
+  }
+
+  /// Playlists that have been fetched before, by title.
+  pub fn playlists(&self) -> Vec<(&String, &CatalogPlaylist)> {
+    let mut playlists = self.contents.playlists.iter().collect::<Vec<_>>();
+    playlists.sort_by(|(_, a), (_, b)| a.title.cmp(&b.title));
+
+    playlists
+  }
+
+  /// The playlist as it was last fetched, with its videos in playlist order.
+  pub fn playlist(&self, id: &str) -> Option<(PlaylistInfo, PlaylistVideos)> {
+    let playlist = self.contents.playlists.get(id)?;
+    let channel = self.contents.channels.get(&playlist.channel_id)?;
+
+    Some((
+      PlaylistInfo {
+        id: id.to_string(),
+        title: playlist.title.clone(),
+        channel: channel.clone(),
+      },
+      PlaylistVideos {
+        videos: playlist
+          .video_ids
+          .iter()
+          .filter_map(|video_id| self.contents.videos.get(video_id).cloned())
+          .collect(),
+        next_cursor: None,
+        total_count: playlist.total_count,
+        details_error: None,
+      },
+    ))
+  }
+
+  /// Replaces whatever was recorded for the playlist with a complete listing of it.
+  pub fn record_playlist(&mut self, info: &PlaylistInfo, videos: &PlaylistVideos) {
+    self
+      .contents
+      .channels
+      .insert(info.channel.id.clone(), info.channel.clone());
+
+    for video in videos.videos.iter() {
+      self.contents.videos.insert(video.id.clone(), video.clone());
+    }
+
+    self.contents.playlists.insert(
+      info.id.clone(),
+      CatalogPlaylist {
+        title: info.title.clone(),
+        channel_id: info.channel.id.clone(),
+        video_ids: videos.videos.iter().map(|video| video.id.clone()).collect(),
+        total_count: videos.total_count,
+      },
+    );
+
+    self.changed();
+  }
+
+  /// Records what was fetched for `query`; a single video is saved without a playlist around it.
+  pub fn record_listing(&mut self, query: &Query, info: &PlaylistInfo, videos: &PlaylistVideos) {
+    let Query::Video(_) = query else {
+      return self.record_playlist(info, videos);
+    };
+
+    self
+      .contents
+      .channels
+      .insert(info.channel.id.clone(), info.channel.clone());
+
+    for video in videos.videos.iter() {
+      self.contents.videos.insert(video.id.clone(), video.clone());
+    }
+
+    self.changed();
+  }
+
+  pub fn video(&self, id: &str) -> Option<&PlaylistVideo> {
+    self.contents.videos.get(id)
+  }
+
+  pub fn is_watched(&self, id: &str) -> bool {
+    self.contents.watched.contains(id)
+  }
+
+  pub fn mark_watched(&mut self, id: &str) {
+    if self.contents.watched.insert(id.to_string()) {
+      self.changed();
+    }
+  }
+
+  /// Thumbnails, in every size, of the videos that have a downloaded file.
+  pub fn downloaded_thumbnails(&self) -> HashSet<String> {
+    self
+      .contents
+      .files
+      .values()
+      .flat_map(|files| files.keys())
+      .filter_map(|id| self.contents.videos.get(id))
+      .flat_map(|video| {
+        std::iter::once(video.thumbnail_url.as_str()).chain(video.thumbnails.urls())
+      })
+      .map(str::to_string)
+      .collect()
+  }
+
+  pub fn file(&self, id: &str, kind: MediaKind) -> Option<&LibraryFile> {
+    self.contents.files.get(&kind)?.get(id)
+  }
+
+  pub fn recorded_format(&self, id: &str, kind: MediaKind) -> Option<&RecordedFormat> {
+    self.file(id, kind).map(|file| &file.format)
+  }
+
+  /// Path of the downloaded file for `id`, if there is one in a format that satisfies `preference`.
+  pub fn cached_path(&self, id: &str, preference: &FormatPreference) -> Option<PathBuf> {
+    let recorded = self.recorded_format(id, preference.kind)?;
+    let path = media_path(&self.library_dir, id, preference.kind, recorded.extension());
+
+    (preference.is_satisfied_by(recorded) && path.exists()).then_some(path)
+  }
+
+  /// Every downloaded file, with the id of its video and where it is in the library.
+  pub fn files(&self) -> impl Iterator<Item = (&str, &LibraryFile, PathBuf)> {
+    self.contents.files.iter().flat_map(move |(kind, files)| {
+      files.iter().map(move |(id, file)| {
+        let path = media_path(&self.library_dir, id, *kind, file.format.extension());
+
+        (id.as_str(), file, path)
+      })
+    })
+  }
+
+  fn insert_file(&mut self, id: &str, path: &Path, format: RecordedFormat) {
+    let size = std::fs::metadata(path).map_or(0, |metadata| metadata.len());
+
+    self
+      .contents
+      .files
+      .entry(format.preference.kind)
+      .or_default()
+      .insert(id.to_string(), LibraryFile { format, size });
+  }
+
+  /// Records a finished download, removing the file it replaces if that had another name.
+  pub fn record_file(&mut self, id: &str, path: &Path, format: RecordedFormat) {
+    let kind = format.preference.kind;
+
+    // a re-download in another container leaves the old file behind under a different name
+    if let Some(previous) = self.recorded_format(id, kind) {
+      let previous_path = media_path(&self.library_dir, id, kind, previous.extension());
+
+      if previous_path != path {
+        _ = std::fs::remove_file(previous_path);
+      }
+    }
+
+    self.insert_file(id, path, format);
+    self.changed();
+  }
+}
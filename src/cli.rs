@@ -0,0 +1,442 @@
+use crate::{
+  catalog::{Catalog, CatalogPlaylist, LibraryFile},
+  downloads::{self, DownloadJob},
+  error::Error,
+  format::{Container, FormatPreference, MediaKind, Quality},
+  query::Query,
+  settings::Settings,
+  youtube::{self, Listing, PlaylistInfo, PlaylistVideo, PlaylistVideos},
+};
+use clap::{Args, Parser, Subcommand};
+use serde::Serialize;
+use std::{
+  collections::HashSet,
+  path::{Path, PathBuf},
+  process::ExitCode,
+  sync::Arc,
+};
+use tokio::task::JoinSet;
+
+/// Some videos couldn't be downloaded; everything else was.
+const EXIT_DOWNLOAD_FAILED: u8 = 1;
+/// YouTube couldn't be reached or the playlist couldn't be fetched. 2 is taken by usage errors.
+const EXIT_FETCH_FAILED: u8 = 3;
+/// The settings file is invalid; the error explains what is wrong with it.
+const EXIT_INVALID_SETTINGS: u8 = 4;
+/// The library's catalog can't be read; it is left as it is.
+const EXIT_INVALID_CATALOG: u8 = 5;
+
+const PLAYLIST_HELP: &str = "A playlist link or ID, a channel link or @handle for its uploads, \
+  or a video link or ID for just that video";
+
+#[derive(Parser)]
+#[command(about = "Browse, watch and download YouTube playlists")]
+pub struct Cli {
+  /// Runs without opening the window, printing JSON to stdout
+  #[command(subcommand)]
+  pub command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+  /// Lists the videos in a playlist
+  List {
+    #[arg(value_name = "PLAYLIST", help = PLAYLIST_HELP)]
+    query: Query,
+  },
+  /// Downloads every video in a playlist that isn't in the library yet
+  Download {
+    #[arg(value_name = "PLAYLIST", help = PLAYLIST_HELP)]
+    query: Query,
+    #[command(flatten)]
+    format: FormatArgs,
+  },
+  /// Updates the saved copy of a playlist, then downloads whatever isn't in the library yet
+  Sync {
+    #[arg(value_name = "PLAYLIST", help = PLAYLIST_HELP)]
+    query: Query,
+    #[command(flatten)]
+    format: FormatArgs,
+  },
+  /// Shows the saved playlists and the downloaded files
+  Library,
+}
+
+/// Each option left out is taken from the default format in the settings file.
+#[derive(Args)]
+pub struct FormatArgs {
+  /// `video` or `audio`
+  #[arg(long)]
+  kind: Option<MediaKind>,
+  /// `highest`, `lowest`, or a resolution such as `720p`
+  #[arg(long)]
+  quality: Option<Quality>,
+  /// `any`, `mp4` or `webm`
+  #[arg(long)]
+  container: Option<Container>,
+}
+
+impl FormatArgs {
+  fn preference(self, default: FormatPreference) -> FormatPreference {
+    FormatPreference {
+      kind: self.kind.unwrap_or(default.kind),
+      quality: self.quality.unwrap_or(default.quality),
+      container: self.container.unwrap_or(default.container),
+    }
+  }
+}
+
+#[derive(Serialize)]
+struct ErrorOutput {
+  error: String,
+  details: Option<String>,
+}
+
+#[derive(Serialize)]
+struct ListOutput<'a> {
+  playlist: &'a PlaylistInfo,
+  videos: &'a [PlaylistVideo],
+}
+
+#[derive(Serialize)]
+struct DownloadedVideo {
+  id: String,
+  path: PathBuf,
+}
+
+#[derive(Serialize)]
+struct FailedVideo {
+  id: String,
+  reason: String,
+}
+
+#[derive(Default, Serialize)]
+struct DownloadReport {
+  downloaded: Vec<DownloadedVideo>,
+  /// Already in the library in the requested format.
+  skipped: Vec<String>,
+  failed: Vec<FailedVideo>,
+}
+
+impl DownloadReport {
+  fn exit_code(&self) -> ExitCode {
+    if self.failed.is_empty() {
+      ExitCode::SUCCESS
+    } else {
+      ExitCode::from(EXIT_DOWNLOAD_FAILED)
+    }
+  }
+}
+
+#[derive(Serialize)]
+struct DownloadOutput<'a> {
+  playlist: &'a PlaylistInfo,
+  #[serde(flatten)]
+  report: DownloadReport,
+}
+
+#[derive(Serialize)]
+struct SyncOutput<'a> {
+  playlist: &'a PlaylistInfo,
+  /// Videos that weren't in the playlist when it was last saved.
+  added: Vec<&'a str>,
+  /// Videos that have been removed from the playlist since it was last saved.
+  removed: Vec<String>,
+  #[serde(flatten)]
+  report: DownloadReport,
+}
+
+#[derive(Serialize)]
+struct LibraryPlaylist<'a> {
+  id: &'a str,
+  #[serde(flatten)]
+  playlist: &'a CatalogPlaylist,
+}
+
+#[derive(Serialize)]
+struct LibraryFileOutput<'a> {
+  id: &'a str,
+  title: Option<&'a str>,
+  path: PathBuf,
+  #[serde(flatten)]
+  file: &'a LibraryFile,
+}
+
+#[derive(Serialize)]
+struct LibraryOutput<'a> {
+  library_dir: &'a Path,
+  playlists: Vec<LibraryPlaylist<'a>>,
+  files: Vec<LibraryFileOutput<'a>>,
+}
+
+fn print_json<T: Serialize>(output: &T) {
+  if let Ok(json) = serde_json::to_string_pretty(output) {
+    println!("{json}");
+  }
+}
+
+fn fetch_failed(error: Error) -> ExitCode {
+  print_json(&ErrorOutput {
+    error: error.to_string(),
+    details: Some(error.details().to_string()),
+  });
+  ExitCode::from(EXIT_FETCH_FAILED)
+}
+
+/// Runs `command` to completion, returning the process's exit code.
+pub async fn run(command: Command) -> ExitCode {
+  let (settings, settings_error) = Settings::load();
+
+  if let Some(error) = settings_error.or_else(|| settings.problems().into_iter().next()) {
+    print_json(&ErrorOutput {
+      error,
+      details: None,
+    });
+    return ExitCode::from(EXIT_INVALID_SETTINGS);
+  }
+
+  let (mut catalog, catalog_error) = Catalog::load(settings.library_dir.clone());
+
+  if let Some(error) = catalog_error {
+    print_json(&ErrorOutput {
+      error,
+      details: None,
+    });
+    return ExitCode::from(EXIT_INVALID_CATALOG);
+  }
+
+  let exit_code = match command {
+    Command::List { query } => {
+      let (playlist_info, playlist_videos_info) = match fetch_playlist(&settings, &query).await {
+        Ok(playlist) => playlist,
+        Err(error) => return fetch_failed(error),
+      };
+
+      catalog.record_listing(&query, &playlist_info, &playlist_videos_info);
+
+      print_json(&ListOutput {
+        playlist: &playlist_info,
+        videos: &playlist_videos_info.videos,
+      });
+
+      ExitCode::SUCCESS
+    }
+    Command::Download { query, format } => {
+      let (playlist_info, playlist_videos_info) = match fetch_playlist(&settings, &query).await {
+        Ok(playlist) => playlist,
+        Err(error) => return fetch_failed(error),
+      };
+
+      catalog.record_listing(&query, &playlist_info, &playlist_videos_info);
+
+      let report = download_missing(
+        &mut catalog,
+        &settings,
+        &playlist_videos_info.videos,
+        format.preference(settings.downloads.default_format),
+      )
+      .await;
+      let exit_code = report.exit_code();
+
+      print_json(&DownloadOutput {
+        playlist: &playlist_info,
+        report,
+      });
+
+      exit_code
+    }
+    Command::Sync { query, format } => {
+      let (playlist_info, playlist_videos_info) = match fetch_playlist(&settings, &query).await {
+        Ok(playlist) => playlist,
+        Err(error) => return fetch_failed(error),
+      };
+
+      let saved_ids = catalog
+        .playlist(&playlist_info.id)
+        .map(|(_, saved_videos_info)| {
+          saved_videos_info
+            .videos
+            .into_iter()
+            .map(|video| video.id)
+            .collect::<HashSet<_>>()
+        })
+        .unwrap_or_default();
+      let fetched_ids = playlist_videos_info
+        .videos
+        .iter()
+        .map(|video| video.id.as_str())
+        .collect::<HashSet<_>>();
+
+      let added = playlist_videos_info
+        .videos
+        .iter()
+        .map(|video| video.id.as_str())
+        .filter(|id| !saved_ids.contains(*id))
+        .collect();
+      let removed = saved_ids
+        .into_iter()
+        .filter(|id| !fetched_ids.contains(id.as_str()))
+        .collect();
+
+      catalog.record_listing(&query, &playlist_info, &playlist_videos_info);
+
+      let report = download_missing(
+        &mut catalog,
+        &settings,
+        &playlist_videos_info.videos,
+        format.preference(settings.downloads.default_format),
+      )
+      .await;
+      let exit_code = report.exit_code();
+
+      print_json(&SyncOutput {
+        playlist: &playlist_info,
+        added,
+        removed,
+        report,
+      });
+
+      exit_code
+    }
+    Command::Library => {
+      let playlists = catalog
+        .playlists()
+        .into_iter()
+        .map(|(id, playlist)| LibraryPlaylist { id, playlist })
+        .collect();
+      let files = catalog
+        .files()
+        .map(|(id, file, path)| LibraryFileOutput {
+          id,
+          title: catalog.video(id).map(|video| video.title.as_str()),
+          path,
+          file,
+        })
+        .collect();
+
+      print_json(&LibraryOutput {
+        library_dir: &settings.library_dir,
+        playlists,
+        files,
+      });
+
+      ExitCode::SUCCESS
+    }
+  };
+
+  catalog.save();
+
+  exit_code
+}
+
+/// Fetches the playlist and all of its videos, failing unless every page could be fetched.
+async fn fetch_playlist(
+  settings: &Settings,
+  query: &Query,
+) -> Result<(PlaylistInfo, PlaylistVideos), Error> {
+  let yt_client = Arc::new(youtube::fetch_youtube_client(settings.youtube.clone()).await?);
+
+  let playlist_info = match youtube::fetch_listing(yt_client.clone(), query).await? {
+    Listing::Playlist(playlist_info) => playlist_info,
+    Listing::Video(video_info, video) => return Ok((video_info, video)),
+  };
+
+  let mut videos = Vec::new();
+  let mut total_count = None;
+
+  youtube::fetch_all_video_pages(yt_client, &playlist_info.id, |page| {
+    // stdout is reserved for the JSON output
+    if let Some(error) = page.details_error {
+      eprintln!(
+        "couldn't look up video details: {error} ({})",
+        error.details()
+      );
+    }
+
+    videos.extend(page.videos);
+    total_count = page.total_count.or(total_count);
+  })
+  .await?;
+
+  Ok((
+    playlist_info,
+    PlaylistVideos {
+      videos,
+      next_cursor: None,
+      total_count,
+      details_error: None,
+    },
+  ))
+}
+
+/// Downloads as many videos at once as the settings allow, skipping the ones already in the
+/// library in `format`.
+async fn download_missing(
+  catalog: &mut Catalog,
+  settings: &Settings,
+  videos: &[PlaylistVideo],
+  format: FormatPreference,
+) -> DownloadReport {
+  let mut report = DownloadReport::default();
+  let mut missing = Vec::new();
+
+  for video in videos {
+    if catalog.cached_path(&video.id, &format).is_some() {
+      report.skipped.push(video.id.clone());
+    } else {
+      missing.push(video);
+    }
+  }
+
+  let mut missing = missing.into_iter();
+  let mut running = JoinSet::new();
+
+  loop {
+    while running.len() < settings.downloads.max_concurrent.max(1) {
+      let Some(video) = missing.next() else {
+        break;
+      };
+
+      // stdout is reserved for the JSON report
+      eprintln!("downloading {} ({})", video.title, video.id);
+
+      let job = DownloadJob {
+        id: video.id.clone(),
+        title: video.title.clone(),
+        format,
+      };
+      let cloned_library_dir = settings.library_dir.clone();
+
+      running.spawn(async move {
+        let id = job.id.clone();
+
+        // run on its own so a download that panics is reported under its id
+        let result =
+          tokio::spawn(async move { downloads::download(job, &cloned_library_dir, |_| {}).await })
+            .await
+            .unwrap_or_else(|error| Err(error.to_string()));
+
+        (id, result)
+      });
+    }
+
+    let Some(finished) = running.join_next().await else {
+      break;
+    };
+    let (id, result) = finished.expect("the task around a download doesn't panic");
+
+    match result {
+      Ok((path, recorded)) => {
+        catalog.record_file(&id, &path, recorded);
+        catalog.save();
+        report.downloaded.push(DownloadedVideo { id, path });
+      }
+      Err(reason) => {
+        eprintln!("couldn't download {id}: {reason}");
+        report.failed.push(FailedVideo { id, reason });
+      }
+    }
+  }
+
+  report
+}
@@ -0,0 +1,636 @@
+use crate::{
+  format::{FormatPreference, RecordedFormat},
+  library::{self, media_path, write_json},
+  settings::DownloadSettings,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+  collections::{HashMap, HashSet, VecDeque},
+  io::SeekFrom,
+  path::{Path, PathBuf},
+  sync::mpsc::Sender,
+  time::{Duration, Instant},
+};
+use tokio::{
+  fs::OpenOptions,
+  io::{AsyncSeekExt, AsyncWriteExt},
+  task::JoinHandle,
+};
+
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(250);
+/// Size of each range request; googlevideo throttles requests for whole files.
+const RANGE_SIZE: u64 = 10 * 1024 * 1024;
+
+fn queue_path(root: &Path) -> PathBuf {
+  root.join("downloads.json")
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct DownloadJob {
+  pub id: String,
+  pub title: String,
+  pub format: FormatPreference,
+}
+
+#[derive(Clone)]
+pub enum DownloadState {
+  Queued,
+  Downloading {
+    downloaded: u64,
+    total: u64,
+    bytes_per_second: u64,
+  },
+  Done,
+  Failed(String),
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct FailedDownload {
+  pub job: DownloadJob,
+  pub reason: String,
+}
+
+/// Sidecar next to a partial file, describing what it is a prefix of so it can be continued.
+#[derive(Serialize, Deserialize)]
+struct PartialDownload {
+  url: String,
+  format: RecordedFormat,
+  total: u64,
+  downloaded: u64,
+}
+
+pub enum DownloadEvent {
+  Started(String),
+  Progress {
+    id: String,
+    downloaded: u64,
+    total: u64,
+    bytes_per_second: u64,
+  },
+  Finished {
+    id: String,
+    path: PathBuf,
+    format: RecordedFormat,
+  },
+  Failed {
+    id: String,
+    reason: String,
+  },
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct QueueState {
+  queue: VecDeque<DownloadJob>,
+  paused: bool,
+  failed: Vec<FailedDownload>,
+}
+
+struct ActiveDownload {
+  job: DownloadJob,
+  handle: JoinHandle<()>,
+}
+
+/// FIFO download queue that runs at most `max_concurrent` downloads at once.
+///
+/// The default format and `max_concurrent` come from the settings file.
+///
+/// The queue is written to disk on every change, so downloads that were queued or
+/// running when the app closed are picked up again on the next launch, continuing
+/// from whatever part of the file was already downloaded.
+pub struct DownloadManager {
+  library_dir: PathBuf,
+  queue: VecDeque<DownloadJob>,
+  active: Vec<ActiveDownload>,
+  max_concurrent: usize,
+  paused: bool,
+  default_format: FormatPreference,
+
+  states: HashMap<String, DownloadState>,
+  failed: Vec<FailedDownload>,
+  /// Goes up whenever a download is queued, starts, finishes or fails, but not with progress.
+  revision: u64,
+  /// The queue file couldn't be read, so it is left as it is rather than overwritten.
+  read_only: bool,
+
+  emit_download_event: Sender<DownloadEvent>,
+}
+
+impl DownloadManager {
+  /// Also returns why the queue file couldn't be read, if it couldn't; the queue then starts
+  /// out empty and isn't saved, and partial files are kept for when the file is fixed.
+  pub fn load(
+    library_dir: PathBuf,
+    settings: &DownloadSettings,
+    emit_download_event: Sender<DownloadEvent>,
+  ) -> (Self, Option<String>) {
+    let path = queue_path(&library_dir);
+
+    let (state, error) = match std::fs::read(&path) {
+      Ok(bytes) => match serde_json::from_slice(&bytes) {
+        Ok(state) => (state, None),
+        Err(error) => (
+          QueueState::default(),
+          Some(format!("{} is invalid: {error}", path.display())),
+        ),
+      },
+      Err(error) if error.kind() == std::io::ErrorKind::NotFound => (QueueState::default(), None),
+      Err(error) => (
+        QueueState::default(),
+        Some(format!("{} can't be read: {error}", path.display())),
+      ),
+    };
+    let read_only = error.is_some();
+
+    if !read_only {
+      // nothing is downloading yet, so partial files are either resumable or left over
+      let resumable_ids = state
+        .queue
+        .iter()
+        .chain(state.failed.iter().map(|failed| &failed.job))
+        .map(|job| job.id.as_str())
+        .collect::<HashSet<_>>();
+      library::remove_incomplete_files(&library_dir, &resumable_ids);
+    }
+
+    let downloads = Self {
+      library_dir,
+      queue: state.queue,
+      active: Vec::new(),
+      max_concurrent: settings.max_concurrent.max(1),
+      paused: state.paused,
+      default_format: settings.default_format,
+      states: HashMap::new(),
+      failed: state.failed,
+      revision: 0,
+      read_only,
+      emit_download_event,
+    };
+
+    (downloads, error)
+  }
+
+  fn save(&mut self) {
+    self.revision += 1;
+
+    if self.read_only {
+      return;
+    }
+
+    // running downloads are stored ahead of the queue so they restart first
+    let queue = self
+      .active
+      .iter()
+      .map(|active| active.job.clone())
+      .chain(self.queue.iter().cloned())
+      .collect();
+
+    write_json(
+      queue_path(&self.library_dir),
+      &QueueState {
+        queue,
+        paused: self.paused,
+        failed: self.failed.clone(),
+      },
+    );
+  }
+
+  /// Points the manager at a library that has already been moved to `library_dir`.
+  pub fn set_library_dir(&mut self, library_dir: PathBuf) {
+    self.library_dir = library_dir;
+    self.save();
+  }
+
+  pub fn queue(&self) -> &VecDeque<DownloadJob> {
+    &self.queue
+  }
+
+  pub fn active(&self) -> impl Iterator<Item = &DownloadJob> {
+    self.active.iter().map(|active| &active.job)
+  }
+
+  pub fn active_count(&self) -> usize {
+    self.active.len()
+  }
+
+  pub fn revision(&self) -> u64 {
+    self.revision
+  }
+
+  pub fn failed(&self) -> &[FailedDownload] {
+    &self.failed
+  }
+
+  pub fn state(&self, id: &str) -> Option<DownloadState> {
+    if self.queue.iter().any(|job| job.id == id) {
+      return Some(DownloadState::Queued);
+    }
+
+    if let Some(failed) = self.failed.iter().find(|failed| failed.job.id == id) {
+      return Some(DownloadState::Failed(failed.reason.clone()));
+    }
+
+    self.states.get(id).cloned()
+  }
+
+  fn contains(&self, id: &str) -> bool {
+    self.is_active(id) || self.queue.iter().any(|job| job.id == id)
+  }
+
+  fn is_active(&self, id: &str) -> bool {
+    self.active.iter().any(|active| active.job.id == id)
+  }
+
+  pub fn is_paused(&self) -> bool {
+    self.paused
+  }
+
+  pub fn default_format(&self) -> FormatPreference {
+    self.default_format
+  }
+
+  /// Takes on changed settings; more downloads start on the next pump if there is room.
+  pub fn apply_settings(&mut self, settings: &DownloadSettings) {
+    self.max_concurrent = settings.max_concurrent.max(1);
+    self.default_format = settings.default_format;
+  }
+
+  pub fn enqueue(&mut self, job: DownloadJob) {
+    if self.contains(&job.id) {
+      return;
+    }
+
+    self.forget(&job.id);
+    self.queue.push_back(job);
+    self.save();
+  }
+
+  /// Queues `job` ahead of everything else, moving it up if it is already waiting.
+  pub fn enqueue_front(&mut self, job: DownloadJob) {
+    if self.is_active(&job.id) {
+      return;
+    }
+
+    self.forget(&job.id);
+    self.queue.retain(|queued| queued.id != job.id);
+    self.queue.push_front(job);
+    self.save();
+  }
+
+  /// Clears any earlier outcome for `id`, e.g. a failure that is being retried.
+  fn forget(&mut self, id: &str) {
+    self.states.remove(id);
+    self.failed.retain(|failed| failed.job.id != id);
+  }
+
+  /// Retries a failed download, continuing from the part that was already downloaded.
+  pub fn retry(&mut self, id: &str) {
+    if let Some(failed) = self.failed.iter().find(|failed| failed.job.id == id) {
+      let job = failed.job.clone();
+      self.enqueue(job);
+    }
+  }
+
+  pub fn pause(&mut self) {
+    self.paused = true;
+
+    // interrupted downloads keep their partial files and go back to the front of the
+    // queue in the order they were started
+    for active in self.active.drain(..).rev() {
+      active.handle.abort();
+      self.states.remove(&active.job.id);
+      self.queue.push_front(active.job);
+    }
+
+    self.save();
+  }
+
+  pub fn resume(&mut self) {
+    self.paused = false;
+    self.save();
+  }
+
+  pub fn cancel(&mut self, id: &str) {
+    if let Some(index) = self.active.iter().position(|active| active.job.id == id) {
+      self.active.remove(index).handle.abort();
+    }
+
+    library::remove_partial_files(&self.library_dir, id);
+
+    self.forget(id);
+    self.queue.retain(|job| job.id != id);
+    self.save();
+  }
+
+  /// Moves a queued download `offset` places towards the back (positive) or front (negative).
+  pub fn move_by(&mut self, id: &str, offset: isize) {
+    let Some(index) = self.queue.iter().position(|job| job.id == id) else {
+      return;
+    };
+
+    let target = index
+      .saturating_add_signed(offset)
+      .min(self.queue.len() - 1);
+
+    if let Some(job) = self.queue.remove(index) {
+      self.queue.insert(target, job);
+      self.save();
+    }
+  }
+
+  /// Frees the slot of a download that reported back, returning it.
+  fn finish(&mut self, id: &str) -> Option<ActiveDownload> {
+    let index = self.active.iter().position(|active| active.job.id == id)?;
+    let active = self.active.remove(index);
+    self.save();
+
+    Some(active)
+  }
+
+  pub fn handle_event(&mut self, event: DownloadEvent) {
+    match event {
+      DownloadEvent::Started(id) => {
+        self.states.insert(
+          id,
+          DownloadState::Downloading {
+            downloaded: 0,
+            total: 0,
+            bytes_per_second: 0,
+          },
+        );
+      }
+      DownloadEvent::Progress {
+        id,
+        downloaded,
+        total,
+        bytes_per_second,
+      } => {
+        // progress can still trickle in from a download that was just cancelled
+        if self.is_active(&id) {
+          self.states.insert(
+            id,
+            DownloadState::Downloading {
+              downloaded,
+              total,
+              bytes_per_second,
+            },
+          );
+        }
+      }
+      DownloadEvent::Finished { id, .. } => {
+        self.finish(&id);
+        self.states.insert(id, DownloadState::Done);
+      }
+      DownloadEvent::Failed { id, reason } => {
+        self.states.remove(&id);
+
+        if let Some(active) = self.finish(&id) {
+          self.failed.push(FailedDownload {
+            job: active.job,
+            reason,
+          });
+          self.save();
+        }
+      }
+    }
+  }
+
+  /// Starts queued downloads until the concurrency limit is reached.
+  pub fn pump(&mut self, ctx: &egui::Context) {
+    if self.paused {
+      return;
+    }
+
+    let mut started = false;
+
+    while self.active.len() < self.max_concurrent {
+      let Some(job) = self.queue.pop_front() else {
+        break;
+      };
+
+      let cloned_download_event_emit = self.emit_download_event.clone();
+      let cloned_ctx = ctx.clone();
+      let cloned_job = job.clone();
+      let cloned_library_dir = self.library_dir.clone();
+
+      let handle = tokio::spawn(async move {
+        let id = cloned_job.id.clone();
+
+        let emit_download_event = |event| {
+          _ = cloned_download_event_emit.send(event);
+          cloned_ctx.request_repaint();
+        };
+
+        let event = match download(cloned_job, &cloned_library_dir, &emit_download_event).await {
+          Ok((path, format)) => DownloadEvent::Finished { id, path, format },
+          Err(reason) => DownloadEvent::Failed { id, reason },
+        };
+
+        emit_download_event(event);
+      });
+
+      self.active.push(ActiveDownload { job, handle });
+      started = true;
+    }
+
+    if started {
+      self.save();
+    }
+  }
+}
+
+fn read_partial_download(sidecar_path: &Path) -> Option<PartialDownload> {
+  serde_json::from_slice(&std::fs::read(sidecar_path).ok()?).ok()
+}
+
+/// Where the body of a partial response starts, from e.g. `Content-Range: bytes 0-99/500`.
+fn content_range_start(response: &reqwest::Response) -> Option<u64> {
+  response
+    .headers()
+    .get(reqwest::header::CONTENT_RANGE)?
+    .to_str()
+    .ok()?
+    .strip_prefix("bytes ")?
+    .split('-')
+    .next()?
+    .parse()
+    .ok()
+}
+
+/// Downloads the video into the library in ranges, continuing a partial file from an earlier
+/// attempt when it is of the same format, and reporting progress at most every
+/// [`PROGRESS_INTERVAL`].
+///
+/// Only `Started` and `Progress` are emitted; the outcome is returned instead.
+pub async fn download(
+  job: DownloadJob,
+  library_dir: &Path,
+  emit_download_event: impl Fn(DownloadEvent),
+) -> Result<(PathBuf, RecordedFormat), String> {
+  let url = format!("https://youtube.com/watch?v={}", job.id);
+  let video = rusty_ytdl::Video::new_with_options(&url, job.format.video_options())
+    .map_err(|error| error.to_string())?;
+
+  let info = video.get_info().await.map_err(|error| error.to_string())?;
+  let chosen = rusty_ytdl::choose_format(&info.formats, &job.format.video_options())
+    .map_err(|error| error.to_string())?;
+  let format = RecordedFormat::new(job.format, &chosen);
+  let path = media_path(library_dir, &job.id, job.format.kind, format.extension());
+
+  if let Some(parent) = path.parent() {
+    _ = std::fs::create_dir_all(parent);
+  }
+
+  // renamed into place once complete, so a partial file is never taken for a cached one
+  let partial_path = library::partial_path(&path);
+  let sidecar_path = library::sidecar_path(&partial_path);
+
+  let total = chosen
+    .content_length
+    .as_deref()
+    .and_then(|content_length| content_length.parse::<u64>().ok())
+    .unwrap_or(0);
+
+  let resumable_length = read_partial_download(&sidecar_path)
+    .filter(|partial| partial.format.itag == format.itag && partial.total == total)
+    .and_then(|_| std::fs::metadata(&partial_path).ok())
+    .map(|metadata| metadata.len())
+    .filter(|length| total == 0 || *length <= total);
+
+  let mut file = match resumable_length {
+    Some(_) => OpenOptions::new().append(true).open(&partial_path).await,
+    None => tokio::fs::File::create(&partial_path).await,
+  }
+  .map_err(|error| error.to_string())?;
+
+  let mut resumed_from = resumable_length.unwrap_or(0);
+  let mut partial = PartialDownload {
+    url,
+    format,
+    total,
+    downloaded: resumed_from,
+  };
+  write_json(sidecar_path.clone(), &partial);
+
+  emit_download_event(DownloadEvent::Started(job.id.clone()));
+
+  let client = reqwest::Client::new();
+  let started_at = Instant::now();
+  let mut last_reported_at = started_at;
+
+  while total == 0 || partial.downloaded < total {
+    let range = if total > 0 {
+      format!(
+        "bytes={}-{}",
+        partial.downloaded,
+        (partial.downloaded + RANGE_SIZE).min(total) - 1
+      )
+    } else {
+      format!("bytes={}-", partial.downloaded)
+    };
+
+    let mut response = client
+      .get(&chosen.url)
+      .header(reqwest::header::RANGE, range)
+      .send()
+      .await
+      .and_then(|response| response.error_for_status())
+      .map_err(|error| error.to_string())?;
+
+    let range_start = partial.downloaded;
+
+    // a server that ignores the range sends the whole file
+    let starts_at = match response.status() {
+      reqwest::StatusCode::PARTIAL_CONTENT => content_range_start(&response).unwrap_or(range_start),
+      _ => 0,
+    };
+
+    if starts_at != range_start {
+      if range_start == 0 {
+        return Err(format!(
+          "the server sent the file from {} instead of the start",
+          format_bytes(starts_at)
+        ));
+      }
+
+      // the partial file can't be continued, so it is downloaded again from the start
+      file.set_len(0).await.map_err(|error| error.to_string())?;
+      file
+        .seek(SeekFrom::Start(0))
+        .await
+        .map_err(|error| error.to_string())?;
+
+      partial.downloaded = 0;
+      resumed_from = 0;
+      write_json(sidecar_path.clone(), &partial);
+
+      // a range other than the one asked for is dropped, and asked for again from the start
+      if starts_at != 0 {
+        continue;
+      }
+    }
+
+    while let Some(chunk) = response.chunk().await.map_err(|error| error.to_string())? {
+      file
+        .write_all(&chunk)
+        .await
+        .map_err(|error| error.to_string())?;
+
+      partial.downloaded += chunk.len() as u64;
+
+      if last_reported_at.elapsed() >= PROGRESS_INTERVAL {
+        last_reported_at = Instant::now();
+
+        let elapsed = started_at.elapsed().as_secs_f64().max(f64::EPSILON);
+
+        emit_download_event(DownloadEvent::Progress {
+          id: job.id.clone(),
+          downloaded: partial.downloaded,
+          total,
+          bytes_per_second: ((partial.downloaded - resumed_from) as f64 / elapsed) as u64,
+        });
+      }
+    }
+
+    file.flush().await.map_err(|error| error.to_string())?;
+    write_json(sidecar_path.clone(), &partial);
+
+    if total == 0 {
+      break;
+    }
+
+    if partial.downloaded == starts_at {
+      return Err(format!(
+        "download stalled at {} of {}",
+        format_bytes(partial.downloaded),
+        format_bytes(total)
+      ));
+    }
+  }
+
+  drop(file);
+
+  tokio::fs::rename(&partial_path, &path)
+    .await
+    .map_err(|error| error.to_string())?;
+  _ = std::fs::remove_file(sidecar_path);
+
+  Ok((path, partial.format))
+}
+
+pub fn format_bytes(bytes: u64) -> String {
+  const UNITS: [&str; 4] = ["B", "KB", "MB", "GB"];
+
+  let mut value = bytes as f64;
+  let mut unit = 0;
+
+  while value >= 1024.0 && unit < UNITS.len() - 1 {
+    value /= 1024.0;
+    unit += 1;
+  }
+
+  if unit == 0 {
+    format!("{bytes} {}", UNITS[unit])
+  } else {
+    format!("{value:.1} {}", UNITS[unit])
+  }
+}
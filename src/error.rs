@@ -0,0 +1,92 @@
+use derive_more::Display;
+
+/// Everything that can go wrong, as a short summary to show and the details behind it.
+#[derive(Display)]
+pub enum Error {
+  #[display(fmt = "YouTube couldn't handle the request")]
+  Api(String),
+  #[display(fmt = "couldn't sign in to YouTube")]
+  Auth(String),
+  #[display(fmt = "the YouTube API quota is used up, try again later")]
+  Quota(String),
+  #[display(fmt = "{} wasn't found, it may be private or deleted", what)]
+  NotFound { what: String, details: String },
+  #[display(fmt = "couldn't reach YouTube")]
+  Network(String),
+  #[display(fmt = "couldn't download {}", title)]
+  Download { title: String, reason: String },
+  #[display(fmt = "couldn't play {}", title)]
+  Playback { title: String, reason: String },
+  #[display(fmt = "couldn't move the library")]
+  Library(String),
+  #[display(fmt = "couldn't load {}, so changes to it aren't saved", what)]
+  Load { what: String, reason: String },
+  #[display(fmt = "couldn't understand \"{}\"", input)]
+  Input { input: String, reason: String },
+}
+
+impl Error {
+  pub fn details(&self) -> &str {
+    match self {
+      Self::Api(details)
+      | Self::Auth(details)
+      | Self::Quota(details)
+      | Self::Network(details)
+      | Self::Library(details) => details,
+      Self::NotFound { details, .. }
+      | Self::Input {
+        reason: details, ..
+      } => details,
+      Self::Download { reason, .. } | Self::Playback { reason, .. } | Self::Load { reason, .. } => {
+        reason
+      }
+    }
+  }
+
+  /// For a request about `what` that came back without it.
+  pub fn not_found(what: impl Into<String>) -> Self {
+    Self::NotFound {
+      what: what.into(),
+      details: "the response had no results".into(),
+    }
+  }
+
+  /// Sorts a failed request about `what` by the reason the API gave for it.
+  pub fn from_api(error: google_apis_common::Error, what: impl Into<String>) -> Self {
+    use google_apis_common::Error as ApiError;
+
+    match error {
+      ApiError::HttpError(error) => Self::Network(error.to_string()),
+      ApiError::Io(error) => Self::Network(error.to_string()),
+      ApiError::MissingAPIKey => Self::Auth("no API key was sent".into()),
+      ApiError::MissingToken(error) => Self::Auth(error.to_string()),
+      ApiError::BadRequest(response) => {
+        let error = &response["error"];
+        let details = error["message"]
+          .as_str()
+          .map_or_else(|| response.to_string(), str::to_string);
+        let reason = error["errors"][0]["reason"].as_str().unwrap_or_default();
+
+        match (error["code"].as_u64(), reason) {
+          (
+            _,
+            "quotaExceeded" | "dailyLimitExceeded" | "rateLimitExceeded" | "userRateLimitExceeded",
+          ) => Self::Quota(details),
+          (Some(401), _) | (_, "keyInvalid" | "authError" | "forbidden") => Self::Auth(details),
+          (Some(404), _) | (_, "playlistItemsNotAccessible") => Self::NotFound {
+            what: what.into(),
+            details,
+          },
+          _ => Self::Api(details),
+        }
+      }
+      ApiError::Failure(response) => Self::Api(format!("HTTP {}", response.status())),
+      error => Self::Api(error.to_string()),
+    }
+  }
+
+  /// For a response that is missing `field`, which YouTube always sends for valid requests.
+  pub fn missing(field: &str) -> Self {
+    Self::Api(format!("the response had no {field}"))
+  }
+}
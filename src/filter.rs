@@ -0,0 +1,252 @@
+use crate::{
+  catalog::Catalog,
+  downloads::{DownloadManager, DownloadState},
+  format::MediaKind,
+  youtube::PlaylistVideo,
+};
+use derive_more::Display;
+
+#[derive(Clone, Copy, Default, PartialEq, Display)]
+pub enum SortBy {
+  #[default]
+  #[display(fmt = "playlist order")]
+  Position,
+  #[display(fmt = "title")]
+  Title,
+  #[display(fmt = "publish date")]
+  Published,
+  #[display(fmt = "duration")]
+  Duration,
+  #[display(fmt = "download state")]
+  DownloadState,
+}
+
+/// How a playlist's videos are ordered and narrowed down in the grid.
+#[derive(Clone, Default, PartialEq)]
+pub struct VideoFilter {
+  pub sort_by: SortBy,
+  pub descending: bool,
+  /// Only downloaded videos when `Some(true)`, only the others when `Some(false)`.
+  pub downloaded: Option<bool>,
+  pub watched: Option<bool>,
+  /// In minutes, 0 for no bound. Videos of unknown length are hidden by either bound.
+  pub min_minutes: u32,
+  pub max_minutes: u32,
+  /// Matched against titles and descriptions, ignoring case.
+  pub text: String,
+}
+
+impl VideoFilter {
+  pub fn is_active(&self) -> bool {
+    self.downloaded.is_some()
+      || self.watched.is_some()
+      || self.min_minutes > 0
+      || self.max_minutes > 0
+      || !self.text.trim().is_empty()
+  }
+
+  /// Playlist positions of the videos that pass the filter, in the chosen order.
+  fn apply(
+    &self,
+    videos: &[PlaylistVideo],
+    catalog: &Catalog,
+    downloads: &DownloadManager,
+    kind: MediaKind,
+  ) -> Vec<usize> {
+    let text = self.text.trim().to_lowercase();
+
+    // downloaded files rank above queued and running downloads, then the rest, then failures
+    let download_rank = |video: &PlaylistVideo| {
+      if catalog.file(&video.id, kind).is_some() {
+        return 3;
+      }
+
+      match downloads.state(&video.id) {
+        Some(DownloadState::Queued | DownloadState::Downloading { .. }) => 2,
+        Some(DownloadState::Failed(_)) => 0,
+        _ => 1,
+      }
+    };
+    let duration = |video: &PlaylistVideo| video.details.as_ref()?.duration;
+
+    let mut shown = videos
+      .iter()
+      .enumerate()
+      .filter(|(_, video)| {
+        self
+          .downloaded
+          .is_none_or(|downloaded| (download_rank(video) == 3) == downloaded)
+      })
+      .filter(|(_, video)| {
+        self
+          .watched
+          .is_none_or(|watched| catalog.is_watched(&video.id) == watched)
+      })
+      .filter(|(_, video)| {
+        if self.min_minutes == 0 && self.max_minutes == 0 {
+          return true;
+        }
+
+        duration(video).is_some_and(|duration| {
+          duration >= u64::from(self.min_minutes) * 60
+            && (self.max_minutes == 0 || duration <= u64::from(self.max_minutes) * 60)
+        })
+      })
+      .filter(|(_, video)| {
+        text.is_empty()
+          || video.title.to_lowercase().contains(&text)
+          || video
+            .details
+            .as_ref()
+            .is_some_and(|details| details.description.to_lowercase().contains(&text))
+      })
+      .collect::<Vec<_>>();
+
+    if self.sort_by == SortBy::Position {
+      if self.descending {
+        shown.reverse();
+      }
+
+      return shown.into_iter().map(|(position, _)| position).collect();
+    }
+
+    // sorting is stable, so reversing around it keeps ties in playlist order either way
+    if self.descending {
+      shown.reverse();
+    }
+
+    match self.sort_by {
+      SortBy::Position => {}
+      SortBy::Title => shown.sort_by_cached_key(|(_, video)| video.title.to_lowercase()),
+      SortBy::Published => shown.sort_by(|(_, a), (_, b)| {
+        let published = |video: &PlaylistVideo| video.details.as_ref()?.published.as_deref();
+        published(a).cmp(&published(b))
+      }),
+      SortBy::Duration => shown.sort_by_key(|(_, video)| duration(video)),
+      SortBy::DownloadState => shown.sort_by_cached_key(|(_, video)| download_rank(video)),
+    }
+
+    if self.descending {
+      shown.reverse();
+    }
+
+    shown.into_iter().map(|(position, _)| position).collect()
+  }
+}
+
+#[derive(PartialEq)]
+struct FilteredKey {
+  filter: VideoFilter,
+  kind: MediaKind,
+  videos_revision: u64,
+  catalog_revision: u64,
+  downloads_revision: u64,
+}
+
+/// The result of a filter, kept until the filter, the playlist, or what is downloaded or
+/// watched changes, rather than worked out again every frame.
+#[derive(Default)]
+pub struct FilteredVideos {
+  key: Option<FilteredKey>,
+  positions: Vec<usize>,
+}
+
+impl FilteredVideos {
+  /// Playlist positions of the videos `filter` lets through, in its order. `videos_revision`
+  /// has to change whenever `videos` does.
+  pub fn get(
+    &mut self,
+    filter: &VideoFilter,
+    videos: &[PlaylistVideo],
+    videos_revision: u64,
+    catalog: &Catalog,
+    downloads: &DownloadManager,
+    kind: MediaKind,
+  ) -> &[usize] {
+    let key = FilteredKey {
+      filter: filter.clone(),
+      kind,
+      videos_revision,
+      catalog_revision: catalog.revision(),
+      downloads_revision: downloads.revision(),
+    };
+
+    if self.key.as_ref() != Some(&key) {
+      self.positions = filter.apply(videos, catalog, downloads, kind);
+      self.key = Some(key);
+    }
+
+    &self.positions
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{settings::DownloadSettings, youtube::VideoDetails};
+  use std::{path::PathBuf, sync::mpsc::channel};
+
+  fn video(id: &str, title: &str, duration: u64) -> PlaylistVideo {
+    PlaylistVideo {
+      id: id.into(),
+      title: title.into(),
+      thumbnail_url: String::new(),
+      thumbnails: Default::default(),
+      details: Some(VideoDetails {
+        duration: Some(duration),
+        ..Default::default()
+      }),
+    }
+  }
+
+  fn positions(filter: VideoFilter) -> Vec<usize> {
+    let videos = [
+      video("a", "Bravo", 60),
+      video("b", "alpha", 120),
+      video("c", "Charlie", 60),
+    ];
+    let (emit_download_event, _) = channel();
+    let (downloads, _) = DownloadManager::load(
+      PathBuf::from("does-not-exist"),
+      &DownloadSettings::default(),
+      emit_download_event,
+    );
+
+    filter.apply(&videos, &Catalog::default(), &downloads, MediaKind::Video)
+  }
+
+  #[test]
+  fn position_order() {
+    assert_eq!(positions(VideoFilter::default()), [0, 1, 2]);
+    assert_eq!(
+      positions(VideoFilter {
+        descending: true,
+        ..Default::default()
+      }),
+      [2, 1, 0]
+    );
+  }
+
+  #[test]
+  fn ties_keep_position_order() {
+    let by_duration = |descending| VideoFilter {
+      sort_by: SortBy::Duration,
+      descending,
+      ..Default::default()
+    };
+
+    assert_eq!(positions(by_duration(false)), [0, 2, 1]);
+    assert_eq!(positions(by_duration(true)), [1, 0, 2]);
+  }
+
+  #[test]
+  fn title_ignores_case() {
+    assert_eq!(
+      positions(VideoFilter {
+        sort_by: SortBy::Title,
+        ..Default::default()
+      }),
+      [1, 0, 2]
+    );
+  }
+}
@@ -0,0 +1,191 @@
+use derive_more::Display;
+use rusty_ytdl::{VideoFormat, VideoOptions, VideoQuality, VideoSearchOptions};
+use serde::{Deserialize, Serialize};
+use std::{str::FromStr, sync::Arc};
+
+pub const RESOLUTIONS: [u32; 8] = [2160, 1440, 1080, 720, 480, 360, 240, 144];
+
+#[derive(Clone, Copy, Default, PartialEq, Eq, Hash, Display, Serialize, Deserialize)]
+pub enum MediaKind {
+  #[default]
+  #[display(fmt = "video")]
+  Video,
+  #[display(fmt = "audio only")]
+  Audio,
+}
+
+impl FromStr for MediaKind {
+  type Err = String;
+
+  fn from_str(kind: &str) -> Result<Self, Self::Err> {
+    match kind {
+      "video" => Ok(Self::Video),
+      "audio" => Ok(Self::Audio),
+      _ => Err(format!(
+        "unknown kind `{kind}`, expected `video` or `audio`"
+      )),
+    }
+  }
+}
+
+#[derive(Clone, Copy, Default, PartialEq, Display, Serialize, Deserialize)]
+pub enum Quality {
+  #[display(fmt = "highest")]
+  Highest,
+  #[default]
+  #[display(fmt = "lowest")]
+  Lowest,
+  /// Closest format at or below the given height, falling back to the closest one above it.
+  #[display(fmt = "{}p", _0)]
+  Resolution(u32),
+}
+
+#[derive(Clone, Copy, Default, PartialEq, Display, Serialize, Deserialize)]
+pub enum Container {
+  #[default]
+  #[display(fmt = "any")]
+  Any,
+  #[display(fmt = "mp4")]
+  Mp4,
+  #[display(fmt = "webm")]
+  Webm,
+}
+
+impl FromStr for Quality {
+  type Err = String;
+
+  /// Parses `highest`, `lowest`, or a resolution such as `720p` or `720`.
+  fn from_str(quality: &str) -> Result<Self, Self::Err> {
+    match quality {
+      "highest" => Ok(Self::Highest),
+      "lowest" => Ok(Self::Lowest),
+      _ => quality
+        .strip_suffix('p')
+        .unwrap_or(quality)
+        .parse()
+        .map(Self::Resolution)
+        .map_err(|_| {
+          format!("unknown quality `{quality}`, expected `highest`, `lowest` or e.g. `720p`")
+        }),
+    }
+  }
+}
+
+impl FromStr for Container {
+  type Err = String;
+
+  fn from_str(container: &str) -> Result<Self, Self::Err> {
+    match container {
+      "any" => Ok(Self::Any),
+      "mp4" => Ok(Self::Mp4),
+      "webm" => Ok(Self::Webm),
+      _ => Err(format!(
+        "unknown container `{container}`, expected `any`, `mp4` or `webm`"
+      )),
+    }
+  }
+}
+
+impl Container {
+  pub fn accepts(&self, container: &str) -> bool {
+    match self {
+      Self::Any => true,
+      Self::Mp4 => container == "mp4",
+      Self::Webm => container == "webm",
+    }
+  }
+}
+
+#[derive(Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct FormatPreference {
+  pub kind: MediaKind,
+  pub quality: Quality,
+  pub container: Container,
+}
+
+impl FormatPreference {
+  fn search_options(&self) -> VideoSearchOptions {
+    let container = self.container;
+
+    match (self.kind, container) {
+      (MediaKind::Audio, Container::Any) => VideoSearchOptions::Audio,
+      (MediaKind::Audio, _) => VideoSearchOptions::Custom(Arc::new(move |format: &VideoFormat| {
+        format.has_audio && !format.has_video && container.accepts(&format.mime_type.container)
+      })),
+      (MediaKind::Video, _) => VideoSearchOptions::Custom(Arc::new(move |format: &VideoFormat| {
+        format.has_video && format.has_audio && container.accepts(&format.mime_type.container)
+      })),
+    }
+  }
+
+  /// Sort key for candidate formats; the smallest key is the one that gets downloaded.
+  fn rank(&self, format: &VideoFormat) -> (bool, u64, u64) {
+    let height = format.height.unwrap_or(0);
+
+    match self.quality {
+      // audio has no resolution to aim for, so the best bitrate wins
+      Quality::Resolution(_) if self.kind == MediaKind::Audio => {
+        (false, 0, u64::MAX - format.bitrate)
+      }
+      Quality::Highest => (false, u64::MAX - height, u64::MAX - format.bitrate),
+      Quality::Lowest => (false, height, format.bitrate),
+      Quality::Resolution(target) => {
+        let target = u64::from(target);
+
+        (
+          height > target,
+          height.abs_diff(target),
+          u64::MAX - format.bitrate,
+        )
+      }
+    }
+  }
+
+  pub fn video_options(&self) -> VideoOptions {
+    let preference = *self;
+
+    VideoOptions {
+      quality: VideoQuality::Custom(
+        self.search_options(),
+        Arc::new(move |a: &VideoFormat, b: &VideoFormat| {
+          preference.rank(a).cmp(&preference.rank(b))
+        }),
+      ),
+      filter: self.search_options(),
+      ..Default::default()
+    }
+  }
+
+  pub fn is_satisfied_by(&self, recorded: &RecordedFormat) -> bool {
+    recorded.preference.kind == self.kind
+      && recorded.preference.quality == self.quality
+      && self.container.accepts(&recorded.container)
+  }
+}
+
+/// The format a file was actually downloaded in, next to the preference that picked it.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct RecordedFormat {
+  pub preference: FormatPreference,
+  pub itag: u64,
+  pub container: String,
+  pub quality_label: Option<String>,
+}
+
+impl RecordedFormat {
+  pub fn new(preference: FormatPreference, format: &VideoFormat) -> Self {
+    Self {
+      preference,
+      itag: format.itag,
+      container: format.mime_type.container.clone(),
+      quality_label: format.quality_label.clone(),
+    }
+  }
+
+  pub fn extension(&self) -> &str {
+    match (self.preference.kind, self.container.as_str()) {
+      (MediaKind::Audio, "mp4") => "m4a",
+      (_, container) => container,
+    }
+  }
+}
@@ -0,0 +1,350 @@
+use crate::library::{write_json, APP_DIR_NAME};
+use egui::{
+  load::{Bytes, BytesLoadResult, BytesLoader, BytesPoll, LoadError},
+  Context,
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+  collections::{HashMap, HashSet},
+  path::{Path, PathBuf},
+  sync::{Arc, Mutex},
+  time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// Past this, the least recently fetched images that aren't pinned are deleted.
+const MAX_CACHE_BYTES: u64 = 256 * 1024 * 1024;
+/// Images older than this are fetched again; the old copy is used if that fails.
+const MAX_AGE_SECS: u64 = 30 * 24 * 60 * 60;
+/// How long after a change the index is saved, so images arriving together are saved at once.
+const SAVE_DELAY: Duration = Duration::from_secs(2);
+
+pub fn default_dir() -> PathBuf {
+  dirs::cache_dir()
+    .map(|dir| dir.join(APP_DIR_NAME))
+    .unwrap_or_else(|| PathBuf::from("cache"))
+    .join("images")
+}
+
+fn now() -> u64 {
+  SystemTime::now()
+    .duration_since(UNIX_EPOCH)
+    .map_or(0, |elapsed| elapsed.as_secs())
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct CachedImage {
+  /// SHA-256 of the image, which is also its file name, so images shared by several
+  /// addresses are stored once.
+  hash: String,
+  size: u64,
+  mime: Option<String>,
+  fetched_at: u64,
+}
+
+enum Loaded {
+  Pending,
+  Ready(Arc<[u8]>, Option<String>),
+  Failed(String),
+}
+
+#[derive(Default)]
+struct State {
+  /// By address.
+  index: HashMap<String, CachedImage>,
+  /// Addresses that are never evicted or refetched.
+  pinned: HashSet<String>,
+  loaded: HashMap<String, Loaded>,
+  /// Files whose address was evicted or now points at a different image.
+  unreferenced: Vec<String>,
+  /// The index has changed and is about to be pruned and saved.
+  save_scheduled: bool,
+}
+
+struct Inner {
+  dir: PathBuf,
+  client: reqwest::Client,
+  state: Mutex<State>,
+}
+
+/// Loads images from the web through a content-addressed cache on disk, so they show up
+/// right away on later launches and while offline.
+///
+/// Registered as a bytes loader, it takes precedence over egui_extras' http loader.
+#[derive(Clone)]
+pub struct ImageCache(Arc<Inner>);
+
+impl ImageCache {
+  pub fn open(dir: PathBuf) -> Self {
+    let index = std::fs::read(dir.join("index.json"))
+      .ok()
+      .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+      .unwrap_or_default();
+
+    Self(Arc::new(Inner {
+      dir,
+      client: reqwest::Client::new(),
+      state: Mutex::new(State {
+        index,
+        ..Default::default()
+      }),
+    }))
+  }
+
+  /// Replaces the images that are kept for good.
+  pub fn set_pinned(&self, pinned: HashSet<String>) {
+    if let Ok(mut state) = self.0.state.lock() {
+      state.pinned = pinned;
+    }
+  }
+
+  fn image_path(dir: &Path, hash: &str) -> PathBuf {
+    dir.join(hash)
+  }
+
+  /// Evicts the oldest images that aren't pinned until the cache fits in its limit, returning
+  /// the files no address refers to anymore.
+  fn prune(state: &mut State) -> Vec<String> {
+    let mut references = HashMap::<String, (usize, u64)>::new();
+    for image in state.index.values() {
+      references
+        .entry(image.hash.clone())
+        .or_insert((0, image.size))
+        .0 += 1;
+    }
+    let mut total_size = references.values().map(|(_, size)| size).sum::<u64>();
+
+    let mut evictable = state
+      .index
+      .iter()
+      .filter(|(uri, _)| !state.pinned.contains(*uri))
+      .map(|(uri, image)| (image.fetched_at, uri.clone()))
+      .collect::<Vec<_>>();
+    evictable.sort();
+
+    for (_, uri) in evictable {
+      if total_size <= MAX_CACHE_BYTES {
+        break;
+      }
+
+      let Some(image) = state.index.remove(&uri) else {
+        continue;
+      };
+
+      if let Some((count, size)) = references.get_mut(&image.hash) {
+        *count -= 1;
+
+        if *count == 0 {
+          total_size -= *size;
+        }
+      }
+
+      state.unreferenced.push(image.hash);
+    }
+
+    std::mem::take(&mut state.unreferenced)
+      .into_iter()
+      .filter(|hash| references.get(hash).is_none_or(|(count, _)| *count == 0))
+      .collect()
+  }
+
+  /// Prunes and saves the index shortly after it changed, off the UI thread.
+  fn schedule_save(&self, state: &mut State) {
+    if state.save_scheduled {
+      return;
+    }
+
+    state.save_scheduled = true;
+    let cloned_cache = self.clone();
+
+    tokio::spawn(async move {
+      tokio::time::sleep(SAVE_DELAY).await;
+
+      let Ok((removed, index)) = cloned_cache.0.state.lock().map(|mut state| {
+        state.save_scheduled = false;
+        (Self::prune(&mut state), state.index.clone())
+      }) else {
+        return;
+      };
+
+      let dir = cloned_cache.0.dir.clone();
+
+      _ = tokio::task::spawn_blocking(move || {
+        for hash in removed {
+          _ = std::fs::remove_file(Self::image_path(&dir, &hash));
+        }
+
+        write_json(dir.join("index.json"), &index);
+      })
+      .await;
+    });
+  }
+
+  async fn download(
+    client: &reqwest::Client,
+    uri: &str,
+  ) -> Result<(Vec<u8>, Option<String>), String> {
+    let response = client
+      .get(uri)
+      .send()
+      .await
+      .and_then(|response| response.error_for_status())
+      .map_err(|error| error.to_string())?;
+
+    let mime = response
+      .headers()
+      .get(reqwest::header::CONTENT_TYPE)
+      .and_then(|mime| mime.to_str().ok())
+      .map(str::to_string);
+    let bytes = response.bytes().await.map_err(|error| error.to_string())?;
+
+    Ok((bytes.to_vec(), mime))
+  }
+
+  /// Reads the image from disk if the cached copy is still good, otherwise fetches and
+  /// stores it, falling back to the old copy when YouTube can't be reached.
+  async fn fetch(
+    &self,
+    uri: &str,
+    cached: Option<CachedImage>,
+    fresh: bool,
+  ) -> Result<(Arc<[u8]>, Option<String>), String> {
+    let Inner { dir, client, .. } = self.0.as_ref();
+
+    if let Some(cached) = cached.as_ref().filter(|_| fresh) {
+      if let Ok(bytes) = tokio::fs::read(Self::image_path(dir, &cached.hash)).await {
+        return Ok((bytes.into(), cached.mime.clone()));
+      }
+    }
+
+    let (bytes, mime) = match Self::download(client, uri).await {
+      Ok(image) => image,
+      Err(error) => {
+        let stale = match cached.as_ref() {
+          Some(cached) => tokio::fs::read(Self::image_path(dir, &cached.hash))
+            .await
+            .ok(),
+          None => None,
+        };
+
+        return match (stale, cached) {
+          (Some(bytes), Some(cached)) => Ok((bytes.into(), cached.mime)),
+          _ => Err(error),
+        };
+      }
+    };
+
+    let hash = Sha256::digest(&bytes)
+      .iter()
+      .map(|byte| format!("{byte:02x}"))
+      .collect::<String>();
+
+    _ = tokio::fs::create_dir_all(dir).await;
+    if tokio::fs::write(Self::image_path(dir, &hash), &bytes)
+      .await
+      .is_ok()
+    {
+      if let Ok(mut state) = self.0.state.lock() {
+        let replaced = state.index.insert(
+          uri.to_string(),
+          CachedImage {
+            hash,
+            size: bytes.len() as u64,
+            mime: mime.clone(),
+            fetched_at: now(),
+          },
+        );
+
+        state.unreferenced.extend(replaced.map(|image| image.hash));
+        self.schedule_save(&mut state);
+      }
+    }
+
+    Ok((bytes.into(), mime))
+  }
+}
+
+impl BytesLoader for ImageCache {
+  fn id(&self) -> &str {
+    egui::generate_loader_id!(ImageCache)
+  }
+
+  fn load(&self, ctx: &Context, uri: &str) -> BytesLoadResult {
+    if !uri.starts_with("https://") && !uri.starts_with("http://") {
+      return Err(LoadError::NotSupported);
+    }
+
+    let Ok(mut state) = self.0.state.lock() else {
+      return Err(LoadError::NotSupported);
+    };
+
+    match state.loaded.get(uri) {
+      Some(Loaded::Pending) => return Ok(BytesPoll::Pending { size: None }),
+      Some(Loaded::Ready(bytes, mime)) => {
+        return Ok(BytesPoll::Ready {
+          size: None,
+          bytes: Bytes::Shared(bytes.clone()),
+          mime: mime.clone(),
+        })
+      }
+      Some(Loaded::Failed(error)) => return Err(LoadError::Loading(error.clone())),
+      None => {}
+    }
+
+    let cached = state.index.get(uri).cloned();
+    let fresh = state.pinned.contains(uri)
+      || cached
+        .as_ref()
+        .is_some_and(|cached| now().saturating_sub(cached.fetched_at) < MAX_AGE_SECS);
+
+    state.loaded.insert(uri.to_string(), Loaded::Pending);
+    drop(state);
+
+    let cloned_cache = self.clone();
+    let cloned_ctx = ctx.clone();
+    let cloned_uri = uri.to_string();
+
+    tokio::spawn(async move {
+      let loaded = match cloned_cache.fetch(&cloned_uri, cached, fresh).await {
+        Ok((bytes, mime)) => Loaded::Ready(bytes, mime),
+        Err(error) => Loaded::Failed(error),
+      };
+
+      if let Ok(mut state) = cloned_cache.0.state.lock() {
+        // forgotten while loading
+        if state.loaded.contains_key(&cloned_uri) {
+          state.loaded.insert(cloned_uri, loaded);
+        }
+      }
+
+      cloned_ctx.request_repaint();
+    });
+
+    Ok(BytesPoll::Pending { size: None })
+  }
+
+  fn forget(&self, uri: &str) {
+    if let Ok(mut state) = self.0.state.lock() {
+      state.loaded.remove(uri);
+    }
+  }
+
+  fn forget_all(&self) {
+    if let Ok(mut state) = self.0.state.lock() {
+      state.loaded.clear();
+    }
+  }
+
+  fn byte_size(&self) -> usize {
+    self.0.state.lock().map_or(0, |state| {
+      state
+        .loaded
+        .values()
+        .map(|loaded| match loaded {
+          Loaded::Ready(bytes, _) => bytes.len(),
+          _ => 0,
+        })
+        .sum()
+    })
+  }
+}
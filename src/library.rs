@@ -0,0 +1,172 @@
+use crate::format::MediaKind;
+use serde::Serialize;
+use std::{
+  collections::HashSet,
+  io,
+  path::{Path, PathBuf},
+};
+
+pub const APP_DIR_NAME: &str = "yt-dl-visualizer";
+const PARTIAL_EXTENSION: &str = "part";
+
+/// Where downloads went before the library directory was configurable.
+pub fn legacy_dir() -> PathBuf {
+  PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/youtube"))
+}
+
+pub fn default_dir() -> PathBuf {
+  dirs::data_dir()
+    .map(|dir| dir.join(APP_DIR_NAME))
+    .unwrap_or_else(|| PathBuf::from("youtube"))
+}
+
+pub fn media_dir(root: &Path, kind: MediaKind) -> PathBuf {
+  match kind {
+    MediaKind::Video => root.to_path_buf(),
+    MediaKind::Audio => root.join("audio"),
+  }
+}
+
+pub fn media_path(root: &Path, id: &str, kind: MediaKind, extension: &str) -> PathBuf {
+  media_dir(root, kind).join(format!("{id}.{extension}"))
+}
+
+/// Writes through a temporary file that then replaces `path`, so a crash or a full disk midway
+/// leaves the previous contents in place.
+pub fn write_json<T: Serialize>(path: PathBuf, value: &T) {
+  if let Some(parent) = path.parent() {
+    _ = std::fs::create_dir_all(parent);
+  }
+
+  let Ok(bytes) = serde_json::to_vec_pretty(value) else {
+    return;
+  };
+
+  let mut temp_path = path.clone().into_os_string();
+  temp_path.push(".tmp");
+
+  if std::fs::write(&temp_path, bytes).is_err() || std::fs::rename(&temp_path, &path).is_err() {
+    _ = std::fs::remove_file(temp_path);
+  }
+}
+
+/// Where a download is written until it completes and gets renamed to `path`.
+pub fn partial_path(path: &Path) -> PathBuf {
+  let mut partial_path = path.as_os_str().to_owned();
+  partial_path.push(".");
+  partial_path.push(PARTIAL_EXTENSION);
+
+  PathBuf::from(partial_path)
+}
+
+/// Where the source and progress of the partial file at `partial_path` are recorded.
+pub fn sidecar_path(partial_path: &Path) -> PathBuf {
+  let mut sidecar_path = partial_path.as_os_str().to_owned();
+  sidecar_path.push(".json");
+
+  PathBuf::from(sidecar_path)
+}
+
+/// For `{id}.{extension}.part` or its `.part.json` sidecar, the id and the partial file's path.
+fn partial_file_of(path: &Path) -> Option<(&str, PathBuf)> {
+  let file_name = path.file_name()?.to_str()?;
+  let partial_name = file_name.strip_suffix(".json").unwrap_or(file_name);
+
+  if !partial_name.ends_with(&format!(".{PARTIAL_EXTENSION}")) {
+    return None;
+  }
+
+  let (id, _) = partial_name.split_once('.')?;
+
+  Some((id, path.with_file_name(partial_name)))
+}
+
+/// Removes partial files that can't be resumed for one of `resumable_ids`, and the empty
+/// placeholders that older versions created before downloading.
+pub fn remove_incomplete_files(root: &Path, resumable_ids: &HashSet<&str>) {
+  for kind in [MediaKind::Video, MediaKind::Audio] {
+    let Ok(entries) = std::fs::read_dir(media_dir(root, kind)) else {
+      continue;
+    };
+
+    for entry in entries.flatten() {
+      let Ok(metadata) = entry.metadata() else {
+        continue;
+      };
+
+      let path = entry.path();
+
+      let is_incomplete = match partial_file_of(&path) {
+        Some((id, partial_path)) => {
+          !resumable_ids.contains(id)
+            || !partial_path.exists()
+            || !sidecar_path(&partial_path).exists()
+        }
+        None => metadata.len() == 0,
+      };
+
+      if metadata.is_file() && is_incomplete {
+        _ = std::fs::remove_file(path);
+      }
+    }
+  }
+}
+
+/// Removes every partial file (and sidecar) kept around to resume downloading `id`.
+pub fn remove_partial_files(root: &Path, id: &str) {
+  for kind in [MediaKind::Video, MediaKind::Audio] {
+    let Ok(entries) = std::fs::read_dir(media_dir(root, kind)) else {
+      continue;
+    };
+
+    for entry in entries.flatten() {
+      let path = entry.path();
+
+      if partial_file_of(&path).is_some_and(|(partial_id, _)| partial_id == id) {
+        _ = std::fs::remove_file(path);
+      }
+    }
+  }
+}
+
+/// Moves everything under `from` into `to`, copying when the two are on different file systems.
+pub fn migrate(from: &Path, to: &Path) -> io::Result<()> {
+  if from == to || !from.exists() {
+    return Ok(());
+  }
+
+  if to.starts_with(from) {
+    return Err(io::Error::new(
+      io::ErrorKind::InvalidInput,
+      "the new library folder can't be inside the current one",
+    ));
+  }
+
+  if !to.exists() {
+    if let Some(parent) = to.parent() {
+      std::fs::create_dir_all(parent)?;
+    }
+
+    if std::fs::rename(from, to).is_ok() {
+      return Ok(());
+    }
+  }
+
+  std::fs::create_dir_all(to)?;
+
+  for entry in std::fs::read_dir(from)? {
+    let entry = entry?;
+    let target = to.join(entry.file_name());
+
+    if entry.file_type()?.is_dir() {
+      migrate(&entry.path(), &target)?;
+    } else if std::fs::rename(entry.path(), &target).is_err() {
+      std::fs::copy(entry.path(), &target)?;
+      std::fs::remove_file(entry.path())?;
+    }
+  }
+
+  _ = std::fs::remove_dir(from);
+
+  Ok(())
+}
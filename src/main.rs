@@ -1,58 +1,120 @@
-use derive_more::Deref;
-use dotenvy::{dotenv, var};
+mod catalog;
+mod cli;
+mod downloads;
+mod error;
+mod filter;
+mod format;
+mod image_cache;
+mod library;
+mod query;
+mod settings;
+mod youtube;
+
+use catalog::Catalog;
+use clap::Parser;
+use cli::Cli;
+use downloads::{format_bytes, DownloadEvent, DownloadJob, DownloadManager, DownloadState};
 use eframe::{App, NativeOptions};
 use egui::{
-  Align, Button, CentralPanel, Color32, Image, Label, Layout, Rgba, RichText, ScrollArea, TextEdit,
-  Vec2,
+  Align, Align2, Area, Button, CentralPanel, Color32, ComboBox, DragValue, Frame, Grid, Image,
+  Label, Layout, ProgressBar, Rgba, RichText, ScrollArea, Sense, SidePanel, TextEdit,
+  TopBottomPanel, Ui, Vec2, Window,
 };
 use egui_video::{AudioDevice, Player};
-use google_youtube3::{
-  api::{
-    ChannelSnippet, PlaylistItem, PlaylistItemListResponse, PlaylistItemSnippet, PlaylistSnippet,
-  },
-  hyper::{self, client::HttpConnector},
-  hyper_rustls::{self, HttpsConnector},
-  oauth2::{ApplicationSecret, InstalledFlowAuthenticator, InstalledFlowReturnMethod},
-  YouTube,
-};
+use error::Error;
+use filter::{FilteredVideos, SortBy, VideoFilter};
+use format::{Container, FormatPreference, MediaKind, Quality, RESOLUTIONS};
+use image_cache::ImageCache;
+use query::{ChannelRef, Query};
+use settings::{Settings, Theme, MAX_CONCURRENT_LIMIT};
 use std::{
+  collections::HashSet,
+  ops::Range,
   path::PathBuf,
+  process::ExitCode,
   sync::{
     mpsc::{channel, Receiver, Sender},
     Arc,
   },
 };
+use tokio::task::AbortHandle;
+use youtube::{
+  ChannelPlaylist, ChannelPlaylists, Listing, PlaylistInfo, PlaylistVideo, PlaylistVideos,
+  SearchKind, SearchResult, SearchResults, VideoDetails, YouTubeChannel, YouTubeClient,
+};
+
+const TILE_WIDTH: f32 = 200.0;
+/// Room for the thumbnail, title, details, download state and buttons of a grid tile.
+const TILE_HEIGHT: f32 = 230.0;
+const THUMBNAIL_SIZE: Vec2 = Vec2::new(TILE_WIDTH, TILE_WIDTH * 9.0 / 16.0);
+const AVATAR_SIZE: Vec2 = Vec2::new(40.0, 40.0);
+/// How many rows past the visible ones keep their thumbnails loaded.
+const THUMBNAIL_KEEP_ROWS: usize = 10;
 
 #[tokio::main]
-async fn main() {
-  dotenv().expect("no .env found");
+async fn main() -> ExitCode {
+  if let Some(command) = Cli::parse().command {
+    return cli::run(command).await;
+  }
+
+  let (settings, settings_error) = Settings::load();
 
   _ = eframe::run_native(
     "YouTube Playlist Player",
-    NativeOptions::default(),
+    NativeOptions {
+      // the theme comes from the settings instead
+      follow_system_theme: false,
+      ..Default::default()
+    },
     Box::new(move |ctx| {
       egui_extras::install_image_loaders(&ctx.egui_ctx);
-
-      let (emit_yt_client, listen_yt_client) = channel::<YouTubeClient>();
-      let (emit_playlist_info, listen_playlist_info) = channel::<PlaylistInfo>();
-      let (emit_playlist_videos_info, listen_playlist_videos_info) = channel::<PlaylistVideos>();
-      let (emit_downloaded_path, listen_downloaded_path) = channel::<PathBuf>();
-      let (emit_download_status, listen_download_status) = channel::<DownloadStatus>();
-
-      let cloned_yt_emit = emit_yt_client.clone();
-      tokio::spawn(async move { cloned_yt_emit.send(Visualizer::fetch_youtube_client().await) });
-
-      Ok(Box::new(Visualizer {
-        current_playlist_id: String::new(),
-        current_page_cursor: None,
+      ctx.egui_ctx.set_visuals(settings.theme.visuals());
+
+      // added last so it is asked before the http loader
+      let image_cache = ImageCache::open(image_cache::default_dir());
+      ctx.egui_ctx.add_bytes_loader(Arc::new(image_cache.clone()));
+
+      let (emit_yt_client, listen_yt_client) = channel::<Result<YouTubeClient, Error>>();
+      let (emit_playlist_info, listen_playlist_info) = channel::<(FetchId, PlaylistInfo)>();
+      let (emit_playlist_videos_info, listen_playlist_videos_info) =
+        channel::<(FetchId, Result<PlaylistVideos, Error>)>();
+      let (emit_downloaded_path, listen_downloaded_path) = channel::<(PathBuf, MediaKind)>();
+      let (emit_download_event, listen_download_event) = channel::<DownloadEvent>();
+      let (emit_library_migration, listen_library_migration) = channel::<Result<PathBuf, Error>>();
+      let (emit_channel_playlists, listen_channel_playlists) =
+        channel::<(FetchId, Result<ChannelPlaylists, Error>)>();
+      let (emit_search_results, listen_search_results) =
+        channel::<(FetchId, Result<SearchResults, Error>)>();
+
+      let (catalog, catalog_error) = Catalog::load(settings.library_dir.clone());
+      image_cache.set_pinned(catalog.downloaded_thumbnails());
+
+      let (downloads, downloads_error) = DownloadManager::load(
+        settings.library_dir.clone(),
+        &settings.downloads,
+        emit_download_event,
+      );
+
+      // explain what is wrong with the settings file right away rather than on first use
+      let settings_draft = settings_error
+        .map(|error| SettingsDraft::new(&settings, vec![error]))
+        .or_else(|| {
+          let problems = settings.problems();
+          (!problems.is_empty()).then(|| SettingsDraft::new(&settings, problems))
+        });
+
+      let mut visualizer = Visualizer {
+        tabs: vec![Tab::new(0)],
+        active_tab: 0,
+        next_tab_id: 1,
 
         current_downloaded_path: None,
 
         yt_client: None,
-        playlist_info: None,
-        playlist_videos_info: None,
+        connecting: false,
 
         tasks: Tasks {
+          emit_yt_client,
           listen_yt_client,
           emit_playlist_info,
           listen_playlist_info,
@@ -60,451 +122,2324 @@ async fn main() {
           listen_playlist_videos_info,
           emit_downloaded_path,
           listen_downloaded_path,
-          emit_download_status,
-          listen_download_status,
+          listen_download_event,
+          emit_library_migration,
+          listen_library_migration,
+          emit_channel_playlists,
+          listen_channel_playlists,
+          emit_search_results,
+          listen_search_results,
         },
 
-        download_status: DownloadStatus::Idle,
+        catalog,
+        image_cache,
+
+        downloads,
+        requested_watch_id: None,
+        format_override: settings.downloads.default_format,
+
+        settings,
+        settings_draft,
+        migrating_library: false,
+        resume_downloads_after_migration: false,
+
+        notifications: Vec::new(),
+        next_notification_id: 0,
 
         current_watching_path: None,
 
         video_player: None,
+        current_listening_path: None,
+        audio_player: None,
         audio_device: AudioDevice::new().expect("failed to create audio device"),
-      }))
+      };
+
+      if let Some(reason) = catalog_error {
+        visualizer.notify(Error::Load {
+          what: "the library catalog".into(),
+          reason,
+        });
+      }
+
+      if let Some(reason) = downloads_error {
+        visualizer.notify(Error::Load {
+          what: "the download queue".into(),
+          reason,
+        });
+      }
+
+      Ok(Box::new(visualizer))
     }),
   );
+
+  ExitCode::SUCCESS
+}
+
+struct Tasks {
+  emit_yt_client: Sender<Result<YouTubeClient, Error>>,
+  listen_yt_client: Receiver<Result<YouTubeClient, Error>>,
+
+  emit_playlist_info: Sender<(FetchId, PlaylistInfo)>,
+  listen_playlist_info: Receiver<(FetchId, PlaylistInfo)>,
+
+  emit_downloaded_path: Sender<(PathBuf, MediaKind)>,
+  listen_downloaded_path: Receiver<(PathBuf, MediaKind)>,
+
+  /// Pages of the playlist as they are fetched, ending with an error if one couldn't be.
+  emit_playlist_videos_info: Sender<(FetchId, Result<PlaylistVideos, Error>)>,
+  listen_playlist_videos_info: Receiver<(FetchId, Result<PlaylistVideos, Error>)>,
+
+  listen_download_event: Receiver<DownloadEvent>,
+
+  emit_library_migration: Sender<Result<PathBuf, Error>>,
+  listen_library_migration: Receiver<Result<PathBuf, Error>>,
+
+  /// Pages of the open channel's playlists, ending with an error if one couldn't be fetched.
+  emit_channel_playlists: Sender<(FetchId, Result<ChannelPlaylists, Error>)>,
+  listen_channel_playlists: Receiver<(FetchId, Result<ChannelPlaylists, Error>)>,
+
+  emit_search_results: Sender<(FetchId, Result<SearchResults, Error>)>,
+  listen_search_results: Receiver<(FetchId, Result<SearchResults, Error>)>,
 }
 
-#[derive(Deref)]
-struct YouTubeClient(YouTube<HttpsConnector<HttpConnector>>);
+/// Identifies a tab for the fetches it starts, so they land in it even after other tabs are
+/// opened or closed.
+type TabId = u64;
 
-#[derive(Default, PartialEq)]
-enum DownloadStatus {
-  #[default]
-  Idle,
-  Pending,
-  Downloading,
-  Finished,
-  Failed,
+/// Tags what a fetch sends back with the tab and the generation of fetches it belongs to.
+#[derive(Clone, Copy)]
+struct FetchId {
+  tab_id: TabId,
+  generation: u64,
 }
 
-struct Tasks {
-  listen_yt_client: Receiver<YouTubeClient>,
+/// A tab's fetches of one kind. Starting over cancels the ones still running, and anything they
+/// already sent is recognized as stale by its generation.
+#[derive(Default)]
+struct Fetches {
+  generation: u64,
+  running: Vec<AbortHandle>,
+}
+
+impl Fetches {
+  fn restart(&mut self) -> u64 {
+    self.cancel();
+    self.generation += 1;
+    self.generation
+  }
+
+  fn track(&mut self, handle: AbortHandle) {
+    self.running.retain(|running| !running.is_finished());
+    self.running.push(handle);
+  }
+
+  fn cancel(&mut self) {
+    for running in self.running.drain(..) {
+      running.abort();
+    }
+  }
+}
+
+/// A playlist, channel or search open in the workspace, with everything fetched for it.
+struct Tab {
+  id: TabId,
+  input: String,
+  /// What is being shown, as last opened from the search box or the library.
+  query: Option<Query>,
+  page_cursor: Option<String>,
+  loading_videos: bool,
+  fetch_after_connect: bool,
+
+  playlist_info: Option<PlaylistInfo>,
+  playlist_videos_info: Option<PlaylistVideos>,
+  /// The shown playlist came from the catalog, and fresh pages are collected separately.
+  showing_cached_playlist: bool,
+  refreshed_videos_info: Option<PlaylistVideos>,
+
+  channel_view: Option<ChannelView>,
+  search_view: Option<SearchView>,
+  video_filter: VideoFilter,
+  filtered_videos: FilteredVideos,
+  /// Goes up whenever `playlist_videos_info` changes.
+  videos_revision: u64,
+  /// Grid thumbnails currently held in memory, released once they scroll far away.
+  loaded_thumbnails: HashSet<String>,
+
+  listing_fetches: Fetches,
+  channel_fetches: Fetches,
+  search_fetches: Fetches,
+}
+
+impl Tab {
+  fn new(id: TabId) -> Self {
+    Self {
+      id,
+      input: String::new(),
+      query: None,
+      page_cursor: None,
+      loading_videos: false,
+      fetch_after_connect: false,
+      playlist_info: None,
+      playlist_videos_info: None,
+      showing_cached_playlist: false,
+      refreshed_videos_info: None,
+      channel_view: None,
+      search_view: None,
+      video_filter: VideoFilter::default(),
+      filtered_videos: FilteredVideos::default(),
+      videos_revision: 0,
+      loaded_thumbnails: HashSet::new(),
+      listing_fetches: Fetches::default(),
+      channel_fetches: Fetches::default(),
+      search_fetches: Fetches::default(),
+    }
+  }
+
+  fn cancel_fetches(&mut self) {
+    self.listing_fetches.cancel();
+    self.channel_fetches.cancel();
+    self.search_fetches.cancel();
+  }
+
+  fn title(&self) -> String {
+    if let Some(channel_view) = &self.channel_view {
+      return channel_view.channel.name.clone();
+    }
+
+    if let Some(search_view) = &self.search_view {
+      return format!("🔎 {}", search_view.terms);
+    }
+
+    match &self.playlist_info {
+      Some(playlist_info) => playlist_info.title.clone(),
+      None if self.loading_videos => "loading...".into(),
+      None => "new tab".into(),
+    }
+  }
+
+  /// Shows the playlist `query` refers to straight from the catalog when it has been fetched
+  /// before.
+  fn show_cached_playlist(&mut self, catalog: &Catalog) {
+    // whatever was still being fetched for the last query would land on top of this one
+    self.listing_fetches.restart();
+    self.loading_videos = false;
+    self.page_cursor = None;
+    self.refreshed_videos_info = None;
+    self.videos_revision += 1;
+
+    let cached_playlist = self
+      .query
+      .as_ref()
+      .and_then(Query::playlist_id)
+      .and_then(|playlist_id| catalog.playlist(&playlist_id));
+
+    match cached_playlist {
+      Some((playlist_info, playlist_videos_info)) => {
+        self.playlist_info = Some(playlist_info);
+        self.playlist_videos_info = Some(playlist_videos_info);
+        self.showing_cached_playlist = true;
+      }
+      None => {
+        self.playlist_info = None;
+        self.playlist_videos_info = None;
+        self.showing_cached_playlist = false;
+      }
+    }
+  }
+
+  /// Adds a fetched page, recording the listing in the catalog once it is complete.
+  fn add_page(&mut self, playlist_videos_page: PlaylistVideos, catalog: &mut Catalog) {
+    self.page_cursor = playlist_videos_page.next_cursor.clone();
+    self.loading_videos = self.page_cursor.is_some();
+    self.videos_revision += 1;
+
+    let videos_info = if self.showing_cached_playlist {
+      &mut self.refreshed_videos_info
+    } else {
+      &mut self.playlist_videos_info
+    };
+
+    match videos_info.as_mut() {
+      Some(playlist_videos_info) => {
+        playlist_videos_info
+          .videos
+          .extend(playlist_videos_page.videos);
+        playlist_videos_info.next_cursor = playlist_videos_page.next_cursor;
+        playlist_videos_info.total_count = playlist_videos_page
+          .total_count
+          .or(playlist_videos_info.total_count);
+      }
+      None => *videos_info = Some(playlist_videos_page),
+    }
+
+    if !self.loading_videos {
+      self.finish_playlist_fetch(catalog);
+    }
+  }
+
+  /// Records a fully fetched listing in the catalog, replacing the cached one on screen.
+  fn finish_playlist_fetch(&mut self, catalog: &mut Catalog) {
+    if self.showing_cached_playlist {
+      self.playlist_videos_info = self.refreshed_videos_info.take();
+      self.showing_cached_playlist = false;
+      self.videos_revision += 1;
+    }
+
+    if let (Some(query), Some(playlist_info), Some(playlist_videos_info)) =
+      (&self.query, &self.playlist_info, &self.playlist_videos_info)
+    {
+      catalog.record_listing(query, playlist_info, playlist_videos_info);
+    }
+  }
+}
+
+/// Results of searching YouTube, shown instead of a playlist while open.
+struct SearchView {
+  terms: String,
+  kind: SearchKind,
+  results: Vec<SearchResult>,
+  next_cursor: Option<String>,
+  loading: bool,
+}
+
+enum SearchAction {
+  Search,
+  LoadMore,
+  Open(SearchResult),
+}
 
-  emit_playlist_info: Sender<PlaylistInfo>,
-  listen_playlist_info: Receiver<PlaylistInfo>,
+/// A channel's uploads and playlists, shown instead of a playlist while it is open.
+struct ChannelView {
+  channel: YouTubeChannel,
+  playlists: Vec<ChannelPlaylist>,
+  loading: bool,
+}
 
-  emit_downloaded_path: Sender<PathBuf>,
-  listen_downloaded_path: Receiver<PathBuf>,
+struct Notification {
+  id: u64,
+  error: Error,
+}
 
-  emit_playlist_videos_info: Sender<PlaylistVideos>,
-  listen_playlist_videos_info: Receiver<PlaylistVideos>,
+/// Edits made in the settings window, only applied once they are saved without problems.
+struct SettingsDraft {
+  settings: Settings,
+  library_dir: String,
+  problems: Vec<String>,
+}
 
-  emit_download_status: Sender<DownloadStatus>,
-  listen_download_status: Receiver<DownloadStatus>,
+impl SettingsDraft {
+  fn new(settings: &Settings, problems: Vec<String>) -> Self {
+    Self {
+      settings: settings.clone(),
+      library_dir: settings.library_dir.to_string_lossy().into_owned(),
+      problems,
+    }
+  }
 }
 
 struct Visualizer {
-  current_playlist_id: String,
-  current_page_cursor: Option<String>,
+  tabs: Vec<Tab>,
+  active_tab: usize,
+  next_tab_id: TabId,
 
   current_downloaded_path: Option<PathBuf>,
 
+  /// Only connected once something has to be fetched, so the library works offline.
   yt_client: Option<Arc<YouTubeClient>>,
-  playlist_info: Option<PlaylistInfo>,
-  playlist_videos_info: Option<PlaylistVideos>,
+  connecting: bool,
 
   tasks: Tasks,
 
-  download_status: DownloadStatus,
+  catalog: Catalog,
+  /// Kept to update which thumbnails are never evicted; egui holds it as an image loader.
+  image_cache: ImageCache,
+
+  downloads: DownloadManager,
+  requested_watch_id: Option<String>,
+  format_override: FormatPreference,
+
+  settings: Settings,
+  /// Open settings window and the edits made in it.
+  settings_draft: Option<SettingsDraft>,
+  migrating_library: bool,
+  resume_downloads_after_migration: bool,
+
+  notifications: Vec<Notification>,
+  next_notification_id: u64,
 
   current_watching_path: Option<PathBuf>,
 
   video_player: Option<Player>,
+
+  current_listening_path: Option<PathBuf>,
+  audio_player: Option<Player>,
   audio_device: AudioDevice,
 }
 
 impl App for Visualizer {
   fn update(&mut self, ctx: &egui::Context, _: &mut eframe::Frame) {
     if let Ok(yt_client) = self.tasks.listen_yt_client.try_recv() {
-      self.yt_client = Some(Arc::new(yt_client));
-    }
+      self.connecting = false;
 
-    if let Ok(playlist_info) = self.tasks.listen_playlist_info.try_recv() {
-      self.playlist_info = Some(playlist_info);
-    }
+      match yt_client {
+        Ok(yt_client) => {
+          self.yt_client = Some(Arc::new(yt_client));
 
-    if let Ok(playlist_videos_info) = self.tasks.listen_playlist_videos_info.try_recv() {
-      self.playlist_videos_info = Some(playlist_videos_info);
-    }
+          // pick up whatever every tab was waiting on the connection for
+          for tab_index in 0..self.tabs.len() {
+            let tab = &mut self.tabs[tab_index];
 
-    if let Ok(download_status) = self.tasks.listen_download_status.try_recv() {
-      if download_status == DownloadStatus::Finished {
-        self.download_status = DownloadStatus::Idle;
-      } else {
-        self.download_status = download_status;
+            if tab.fetch_after_connect {
+              tab.fetch_after_connect = false;
+              self.refresh_playlist(tab_index, ctx);
+            }
+
+            let tab = &self.tabs[tab_index];
+
+            if tab
+              .channel_view
+              .as_ref()
+              .is_some_and(|channel_view| channel_view.loading)
+            {
+              self.fetch_channel_playlists(tab_index, ctx);
+            }
+
+            let tab = &self.tabs[tab_index];
+
+            if tab
+              .search_view
+              .as_ref()
+              .is_some_and(|search_view| search_view.loading)
+            {
+              self.fetch_search_results(tab_index, ctx);
+            }
+          }
+        }
+        Err(error) => {
+          for tab in self.tabs.iter_mut() {
+            tab.fetch_after_connect = false;
+            tab.loading_videos = false;
+
+            if let Some(channel_view) = tab.channel_view.as_mut() {
+              channel_view.loading = false;
+            }
+
+            if let Some(search_view) = tab.search_view.as_mut() {
+              search_view.loading = false;
+            }
+          }
+
+          self.notify(error);
+        }
       }
     }
 
-    if let Ok(downloaded_path) = self.tasks.listen_downloaded_path.try_recv() {
-      if self.current_watching_path.is_none() {
-        if let Ok(video_player) = Player::new(ctx, &downloaded_path.to_string_lossy().to_string()) {
-          self.video_player = Some(video_player);
-          self.current_watching_path = Some(downloaded_path.clone());
+    // results for tabs that have been closed since, or that have started over, are dropped
+    while let Ok((fetch_id, search_results)) = self.tasks.listen_search_results.try_recv() {
+      let Some(tab) = fetching_tab(&mut self.tabs, fetch_id, |tab| &tab.search_fetches) else {
+        continue;
+      };
+      let Some(search_view) = tab.search_view.as_mut() else {
+        continue;
+      };
+
+      search_view.loading = false;
+
+      match search_results {
+        Ok(search_results) => {
+          search_view.next_cursor = search_results.next_cursor;
+          search_view.results.extend(search_results.results);
         }
+        Err(error) => self.notify(error),
       }
+    }
 
-      self.current_downloaded_path = Some(downloaded_path);
+    while let Ok((fetch_id, channel_playlists)) = self.tasks.listen_channel_playlists.try_recv() {
+      let Some(tab) = fetching_tab(&mut self.tabs, fetch_id, |tab| &tab.channel_fetches) else {
+        continue;
+      };
+      let Some(channel_view) = tab.channel_view.as_mut() else {
+        continue;
+      };
+
+      match channel_playlists {
+        Ok(channel_playlists) => {
+          channel_view.loading = channel_playlists.next_cursor.is_some();
+          channel_view.playlists.extend(channel_playlists.playlists);
+        }
+        Err(error) => {
+          channel_view.loading = false;
+          self.notify(error);
+        }
+      }
     }
 
-    CentralPanel::default().show(ctx, |ui| {
-      ui.with_layout(Layout::left_to_right(Align::TOP), |ui| {
-        ui.label("YouTube Playlist ID:");
-        ui.add(TextEdit::singleline(&mut self.current_playlist_id));
-
-        if ui.button("🔍").clicked() {
-          let cloned_playlist_info_emit = self.tasks.emit_playlist_info.clone();
-          let cloned_playlist_videos_info_emit = self.tasks.emit_playlist_videos_info.clone();
-          let Some(yt_client) = &self.yt_client else {
-            return;
-          };
+    while let Ok((fetch_id, playlist_info)) = self.tasks.listen_playlist_info.try_recv() {
+      if let Some(tab) = fetching_tab(&mut self.tabs, fetch_id, |tab| &tab.listing_fetches) {
+        tab.playlist_info = Some(playlist_info);
+      }
+    }
 
-          let cloned_yt_client = yt_client.clone();
-          let cloned_playlist_id = self.current_playlist_id.clone();
-          let cloned_cursor = self.current_page_cursor.clone();
+    while let Ok((fetch_id, playlist_videos_page)) =
+      self.tasks.listen_playlist_videos_info.try_recv()
+    {
+      let Some(tab) = fetching_tab(&mut self.tabs, fetch_id, |tab| &tab.listing_fetches) else {
+        continue;
+      };
 
-          tokio::spawn(async move {
-            if let Some(playlist_info) =
-              Self::fetch_playlist_info(cloned_yt_client.clone(), &cloned_playlist_id).await
-            {
-              _ = cloned_playlist_info_emit.send(playlist_info);
-            }
+      match playlist_videos_page {
+        Ok(mut playlist_videos_page) => {
+          let details_error = playlist_videos_page.details_error.take();
+          tab.add_page(playlist_videos_page, &mut self.catalog);
 
-            if let Some(playlist_videos_info) = Self::fetch_video_page_with_cursor(
-              cloned_yt_client.clone(),
-              &cloned_playlist_id,
-              cloned_cursor,
-            )
-            .await
-            {
-              _ = cloned_playlist_videos_info_emit.send(playlist_videos_info);
-            }
-          });
+          if let Some(error) = details_error {
+            self.notify(error);
+          }
         }
-      });
+        Err(error) => {
+          // a failed refresh keeps showing the cached listing rather than a partial one
+          tab.loading_videos = false;
+          tab.refreshed_videos_info = None;
+          self.notify(error);
+        }
+      }
+    }
 
-      ScrollArea::vertical().show(ui, |ui| {
-        match self.download_status {
-          DownloadStatus::Downloading => {
-            ui.label("downloading video...");
+    while let Ok(download_event) = self.tasks.listen_download_event.try_recv() {
+      match &download_event {
+        DownloadEvent::Finished { id, path, format } => {
+          self.catalog.record_file(id, path, format.clone());
+          self
+            .image_cache
+            .set_pinned(self.catalog.downloaded_thumbnails());
+
+          if self.requested_watch_id.as_ref() == Some(id) {
+            self.requested_watch_id = None;
+            _ = self
+              .tasks
+              .emit_downloaded_path
+              .send((path.clone(), format.preference.kind));
           }
-          DownloadStatus::Failed => {
-            ui.label("download failed");
-          }
-          _ => {}
         }
+        DownloadEvent::Failed { id, reason } => {
+          if self.requested_watch_id.as_ref() == Some(id) {
+            self.requested_watch_id = None;
+          }
 
-        if self.video_player.is_some() && ui.button("back").clicked() {
-          self.current_watching_path = None;
-          self.video_player = None;
-          return;
+          let title = self
+            .downloads
+            .active()
+            .find(|job| &job.id == id)
+            .map_or_else(|| id.clone(), |job| job.title.clone());
+
+          self.notify(Error::Download {
+            title,
+            reason: reason.clone(),
+          });
         }
+        _ => {}
+      }
 
-        if let Some(video_player) = self.video_player.as_mut() {
-          video_player.ui(ui, video_player.size);
-          return;
+      self.downloads.handle_event(download_event);
+    }
+
+    if let Ok(migration) = self.tasks.listen_library_migration.try_recv() {
+      match migration {
+        Ok(library_dir) => {
+          self.settings.library_dir = library_dir.clone();
+          self.settings.save();
+          self.catalog.set_library_dir(library_dir.clone());
+          self.downloads.set_library_dir(library_dir);
         }
+        Err(error) => self.notify(error),
+      }
 
-        if let Some(playlist_info) = &self.playlist_info {
-          ui.with_layout(Layout::left_to_right(Align::TOP), |ui| {
-            ui.add(
-              Image::from_uri(&playlist_info.channel.avatar_url).max_size(Vec2::new(40.0, 40.0)),
-            );
-            ui.with_layout(Layout::top_down(Align::TOP), |ui| {
-              ui.label(RichText::new(&playlist_info.title).size(18.0));
-              ui.with_layout(Layout::left_to_right(Align::TOP), |ui| {
-                ui.label("by");
-                ui.hyperlink_to(
-                  &playlist_info.channel.name,
-                  format!("https://youtube.com/channel/{}", &playlist_info.channel.id),
-                );
-              });
-            });
-          });
+      self.migrating_library = false;
+
+      if self.resume_downloads_after_migration {
+        self.downloads.resume();
+      }
+    }
+
+    // while the library is moving, changes wait to be written into its new location
+    if !self.migrating_library {
+      self.catalog.save_in_background();
+    }
+
+    if self.catalog.has_unsaved_changes() {
+      ctx.request_repaint_after(catalog::SAVE_DELAY);
+    }
+
+    self.downloads.pump(ctx);
+
+    if let Ok((downloaded_path, kind)) = self.tasks.listen_downloaded_path.try_recv() {
+      let opened = match kind {
+        MediaKind::Video if self.current_watching_path.is_some() => Ok(()),
+        MediaKind::Video => {
+          Player::new(ctx, &downloaded_path.to_string_lossy().to_string()).map(|video_player| {
+            self.video_player = Some(video_player);
+            self.current_watching_path = Some(downloaded_path.clone());
+          })
+        }
+        // the track only goes to the audio device; its (empty) picture is never shown
+        MediaKind::Audio => Player::new(ctx, &downloaded_path.to_string_lossy().to_string())
+          .and_then(|player| player.with_audio(&mut self.audio_device))
+          .map(|audio_player| {
+            self.audio_player = Some(audio_player);
+            self.current_listening_path = Some(downloaded_path.clone());
+          }),
+      };
+
+      if opened.is_ok() {
+        if let Some(id) = downloaded_path.file_stem().and_then(|id| id.to_str()) {
+          self.catalog.mark_watched(id);
         }
+      }
 
-        ui.separator();
+      if let Err(error) = opened {
+        let title = downloaded_path
+          .file_stem()
+          .and_then(|id| self.catalog.video(id.to_str()?))
+          .map_or_else(
+            || downloaded_path.to_string_lossy().into_owned(),
+            |video| video.title.clone(),
+          );
 
-        if let Some(playlist_videos_info) = &self.playlist_videos_info {
-          ui.with_layout(Layout::right_to_left(Align::TOP), |ui| {
-            if ui
-              .add(
-                Button::new(RichText::new("download all videos").color(Color32::WHITE))
-                  .fill(Rgba::from_rgb(0.0, 0.25, 0.40)),
-              )
-              .clicked()
-            {
-              let cloned_download_status_emit = self.tasks.emit_download_status.clone();
+        self.notify(Error::Playback {
+          title,
+          reason: error.to_string(),
+        });
+      }
 
-              _ = cloned_download_status_emit
-                .clone()
-                .send(DownloadStatus::Pending);
+      self.current_downloaded_path = Some(downloaded_path);
+    }
 
-              let id_path_map = playlist_videos_info
-                .videos
-                .iter()
-                .filter_map(|PlaylistVideo { id, .. }| {
-                  let path = PathBuf::from(format!(
-                    concat!(env!("CARGO_MANIFEST_DIR"), "/youtube/{}.mp4"),
-                    id
-                  ));
+    let mut stopped_listening = false;
 
-                  (!path.exists()).then_some((id, path))
-                })
-                .map(move |(id, path)| {
-                  let id = id.clone();
-
-                  async move {
-                    let options = rusty_ytdl::VideoOptions {
-                      quality: rusty_ytdl::VideoQuality::Lowest,
-                      filter: rusty_ytdl::VideoSearchOptions::VideoAudio,
-                      ..Default::default()
-                    };
-
-                    let video = rusty_ytdl::Video::new_with_options(
-                      format!("https://youtube.com/watch?v={id}"),
-                      options,
-                    )
-                    .expect("failed to create video downloader");
-
-                    if let Some(parent) = path.parent() {
-                      _ = std::fs::create_dir_all(parent);
-                    }
-
-                    _ = std::fs::write(&path, b"");
-                    _ = video.download(&path).await;
-                  }
-                })
-                .collect::<Vec<_>>();
+    if let Some(audio_player) = self.audio_player.as_mut() {
+      TopBottomPanel::bottom("now_playing").show(ctx, |ui| {
+        ui.with_layout(Layout::left_to_right(Align::Center), |ui| {
+          if ui.button("⏹").clicked() {
+            stopped_listening = true;
+          }
 
-              tokio::spawn(async move {
-                _ = cloned_download_status_emit.send(DownloadStatus::Downloading);
-                futures_util::future::join_all(id_path_map).await;
-                _ = cloned_download_status_emit.send(DownloadStatus::Finished);
-              });
+          let title = self
+            .current_listening_path
+            .as_ref()
+            .and_then(|path| path.file_stem())
+            .and_then(|id| self.catalog.video(id.to_str()?))
+            .map_or("unknown track", |video| video.title.as_str());
+
+          ui.label(RichText::new(format!("🎵 {title}")).strong());
+          audio_player.ui(ui, Vec2::new(ui.available_width(), 32.0));
+        });
+      });
+    }
+
+    if stopped_listening {
+      self.current_listening_path = None;
+      self.audio_player = None;
+    }
+
+    self.settings_window_ui(ctx);
+    self.notifications_ui(ctx);
+
+    SidePanel::right("downloads").show(ctx, |ui| {
+      ui.heading("Account");
+
+      match &self.yt_client {
+        Some(yt_client) if yt_client.uses_account() => {
+          ui.with_layout(Layout::left_to_right(Align::TOP), |ui| {
+            if ui.button("sign out").clicked() {
+              self.sign_out(ctx, false);
+            }
+
+            if ui.button("switch account").clicked() {
+              self.sign_out(ctx, true);
             }
           });
-          ui.with_layout(
-            Layout::left_to_right(Align::TOP).with_main_wrap(true),
-            |ui| {
-              for video in playlist_videos_info.videos.iter() {
-                ui.with_layout(Layout::top_down(Align::TOP).with_main_wrap(true), |ui| {
-                  ui.add(Image::from_uri(&video.thumbnail_url).max_width(200.0));
-
-                  ui.add_sized([200.0, 32.0], Label::new(&video.title).wrap());
-
-                  if ui.button("watch").clicked() {
-                    let id = video.id.clone();
-
-                    let path = PathBuf::from(format!(
-                      concat!(env!("CARGO_MANIFEST_DIR"), "/youtube/{}.mp4"),
-                      id
-                    ));
-
-                    if path.exists() {
-                      _ = self.tasks.emit_downloaded_path.send(path);
-                    } else {
-                      let cloned_downloaded_path_emit = self.tasks.emit_downloaded_path.clone();
-                      let cloned_download_status_emit = self.tasks.emit_download_status.clone();
-
-                      tokio::spawn(async move {
-                        _ = cloned_download_status_emit.send(DownloadStatus::Pending);
-
-                        let options = rusty_ytdl::VideoOptions {
-                          quality: rusty_ytdl::VideoQuality::Lowest,
-                          filter: rusty_ytdl::VideoSearchOptions::VideoAudio,
-                          ..Default::default()
-                        };
-
-                        let video = rusty_ytdl::Video::new_with_options(
-                          format!("https://youtube.com/watch?v={id}"),
-                          options,
-                        )
-                        .expect("failed to create video downloader");
-
-                        if let Some(parent) = path.parent() {
-                          _ = std::fs::create_dir_all(parent);
-                        }
+        }
+        Some(_) => {
+          ui.label("using an API key");
+        }
+        None if self.connecting => {
+          ui.label("connecting...");
+        }
+        None => {
+          if ui.button("sign in").clicked() {
+            self.connect(ctx);
+          }
+        }
+      }
+
+      ui.separator();
+
+      ui.heading("Library");
+      ui.add(Label::new(self.settings.library_dir.to_string_lossy()).truncate());
+
+      if self.migrating_library {
+        ui.with_layout(Layout::left_to_right(Align::TOP), |ui| {
+          ui.spinner();
+          ui.label("moving library...");
+        });
+      } else if ui.button("change folder…").clicked() {
+        if let Some(library_dir) = rfd::FileDialog::new()
+          .set_directory(&self.settings.library_dir)
+          .pick_folder()
+        {
+          self.start_library_migration(library_dir, ctx);
+        }
+      }
 
-                        _ = std::fs::write(&path, b"");
-                        _ = cloned_download_status_emit.send(DownloadStatus::Downloading);
+      let mut opened_playlist_id = None;
 
-                        if video.download(&path).await.is_ok() {
-                          _ = cloned_downloaded_path_emit.send(path);
-                          _ = cloned_download_status_emit.send(DownloadStatus::Finished);
-                        }
-                      });
-                    }
-                  }
-                });
-              }
-            },
-          );
-        } else {
-          ui.label("Enter a YouTube playlist ID in the textbox above and click the search button");
+      ui.collapsing("playlists", |ui| {
+        for (id, playlist) in self.catalog.playlists() {
+          let label = format!("{} ({})", playlist.title, playlist.video_ids.len());
+
+          if ui
+            .selectable_label(
+              self.tabs[self.active_tab]
+                .playlist_info
+                .as_ref()
+                .is_some_and(|playlist_info| playlist_info.id == *id),
+              label,
+            )
+            .clicked()
+          {
+            opened_playlist_id = Some(id.clone());
+          }
         }
       });
-    });
-  }
-}
 
-struct YouTubeChannel {
-  id: String,
-  name: String,
-  avatar_url: String,
-}
+      if let Some(id) = opened_playlist_id {
+        let tab = &mut self.tabs[self.active_tab];
+        tab.input = id.clone();
+        tab.query = Some(Query::Playlist(id));
+        tab.channel_view = None;
+        tab.search_view = None;
+        tab.show_cached_playlist(&self.catalog);
+
+        // browsing the library shouldn't start signing in, only refresh when already online
+        if self.yt_client.is_some() {
+          self.refresh_playlist(self.active_tab, ctx);
+        }
+      }
 
-struct PlaylistInfo {
-  id: String,
-  title: String,
-  channel: YouTubeChannel,
-}
+      ui.separator();
 
-struct PlaylistVideo {
-  id: String,
-  title: String,
-  thumbnail_url: String,
-}
+      ui.heading("Downloads");
+      ui.label(format!(
+        "{} running, {} queued",
+        self.downloads.active_count(),
+        self.downloads.queue().len()
+      ));
 
-struct PlaylistVideos {
-  videos: Vec<PlaylistVideo>,
-  next_cursor: Option<String>,
-}
+      ui.with_layout(Layout::left_to_right(Align::TOP), |ui| {
+        if self.downloads.is_paused() {
+          if ui.button("▶ resume").clicked() {
+            self.downloads.resume();
+          }
+        } else if ui.button("⏸ pause").clicked() {
+          self.downloads.pause();
+        }
 
-impl Visualizer {
-  async fn fetch_youtube_client() -> YouTubeClient {
-    let secret = ApplicationSecret {
-      client_id: var("CLIENT_ID").expect("no CLIENT_ID env var found"),
-      client_secret: var("CLIENT_SECRET").expect("no CLIENT_SECRET env var found"),
-      auth_uri: var("AUTH_URI").expect("no AUTH_URI env var found"),
-      token_uri: var("TOKEN_URI").expect("no TOKEN_URI env var found"),
-      redirect_uris: vec!["http://localhost:6969".into()],
-      project_id: None,
-      client_email: None,
-      auth_provider_x509_cert_url: None,
-      client_x509_cert_url: None,
-    };
+        let mut max_concurrent = self.settings.downloads.max_concurrent;
 
-    let auth = InstalledFlowAuthenticator::builder(
-      secret,
-      InstalledFlowReturnMethod::HTTPPortRedirect(6969),
-    )
-    .build()
-    .await
-    .expect("failed to authenticate");
-
-    YouTubeClient(YouTube::new(
-      hyper::Client::builder().build(
-        hyper_rustls::HttpsConnectorBuilder::new()
-          .with_native_roots()
-          .unwrap()
-          .https_or_http()
-          .enable_http1()
-          .build(),
-      ),
-      auth,
-    ))
-  }
+        ui.label("at once:");
+        if ui
+          .add(DragValue::new(&mut max_concurrent).range(1..=MAX_CONCURRENT_LIMIT))
+          .changed()
+        {
+          self.settings.downloads.max_concurrent = max_concurrent;
+          self.save_download_settings();
+        }
+      });
 
-  async fn fetch_channel(yt_client: Arc<YouTubeClient>, user_id: &str) -> Option<YouTubeChannel> {
-    let (_, channels) = yt_client
-      .channels()
-      .list(&vec!["snippet".into(), "contentDetails".into()])
-      .add_id(user_id)
-      .doit()
-      .await
-      .ok()?;
+      let mut default_format = self.settings.downloads.default_format;
+
+      if format_picker_ui(ui, "default_format", &mut default_format) {
+        self.settings.downloads.default_format = default_format;
+        self.save_download_settings();
+      }
 
-    channels.items?.into_iter().next().and_then(|channel| {
-      let ChannelSnippet {
-        title, thumbnails, ..
-      } = channel.snippet?;
+      ui.separator();
 
-      Some(YouTubeChannel {
-        id: user_id.to_string(),
-        name: title?,
-        avatar_url: thumbnails?.default?.url?,
-      })
-    })
-  }
+      ScrollArea::vertical().show(ui, |ui| {
+        let mut cancelled_id = None;
+        let mut moved = None;
 
-  async fn fetch_playlist_info(
-    yt_client: Arc<YouTubeClient>,
-    playlist_id: &str,
-  ) -> Option<PlaylistInfo> {
-    let (_, playlists) = yt_client
-      .playlists()
-      .list(&vec!["snippet".into()])
-      .add_id(playlist_id)
-      .doit()
-      .await
-      .ok()?;
+        let mut retried_id = None;
+
+        for job in self.downloads.active() {
+          ui.with_layout(Layout::left_to_right(Align::TOP), |ui| {
+            if ui.small_button("✖").clicked() {
+              cancelled_id = Some(job.id.clone());
+            }
 
-    let PlaylistSnippet {
-      channel_id, title, ..
-    } = playlists.items?.into_iter().next()?.snippet?;
+            ui.add(Label::new(&job.title).truncate());
+          });
 
-    Some(PlaylistInfo {
-      id: playlist_id.to_string(),
-      title: title?,
-      channel: Self::fetch_channel(yt_client, &channel_id?).await?,
-    })
-  }
+          if let Some(state) = self.downloads.state(&job.id) {
+            download_state_ui(ui, &state);
+          }
+        }
 
-  async fn fetch_video_page_with_cursor(
-    yt_client: Arc<YouTubeClient>,
-    playlist_id: &str,
-    cursor: Option<String>,
-  ) -> Option<PlaylistVideos> {
-    let mut videos_query = yt_client
-      .playlist_items()
-      .list(&vec!["snippet".into(), "contentDetails".into()])
-      .playlist_id(playlist_id);
-
-    if let Some(cursor) = cursor {
-      videos_query = videos_query.page_token(&cursor);
-    }
-
-    let (_, videos) = videos_query.doit().await.ok()?;
-
-    let PlaylistItemListResponse {
-      items: videos,
-      next_page_token: next_cursor,
-      ..
-    } = videos;
-
-    Some(PlaylistVideos {
-      videos: videos?
-        .into_iter()
-        .filter_map(
-          |PlaylistItem {
-             snippet,
-             content_details,
-             ..
-           }| {
-            let PlaylistItemSnippet {
-              title, thumbnails, ..
-            } = snippet?;
-
-            Some(PlaylistVideo {
-              id: content_details?.video_id?,
-              title: title?,
-              thumbnail_url: thumbnails?.default?.url?,
-            })
-          },
-        )
-        .collect::<Vec<_>>(),
-      next_cursor,
-    })
-  }
+        for job in self.downloads.queue() {
+          ui.with_layout(Layout::left_to_right(Align::TOP), |ui| {
+            if ui.small_button("✖").clicked() {
+              cancelled_id = Some(job.id.clone());
+            }
+
+            if ui.small_button("⏶").clicked() {
+              moved = Some((job.id.clone(), -1));
+            }
+
+            if ui.small_button("⏷").clicked() {
+              moved = Some((job.id.clone(), 1));
+            }
+
+            ui.add(Label::new(&job.title).truncate());
+          });
+        }
+
+        for failed in self.downloads.failed() {
+          let job = &failed.job;
+
+          ui.with_layout(Layout::left_to_right(Align::TOP), |ui| {
+            // also deletes what was downloaded so far
+            if ui.small_button("✖").on_hover_text("discard").clicked() {
+              cancelled_id = Some(job.id.clone());
+            }
+
+            if ui.small_button("⟳").on_hover_text("retry").clicked() {
+              retried_id = Some(job.id.clone());
+            }
+
+            ui.add(Label::new(&job.title).truncate());
+          });
+
+          if let Some(state) = self.downloads.state(&job.id) {
+            download_state_ui(ui, &state);
+          }
+        }
+
+        if let Some(id) = retried_id {
+          self.downloads.retry(&id);
+        }
+
+        if let Some(id) = cancelled_id {
+          if self.requested_watch_id.as_ref() == Some(&id) {
+            self.requested_watch_id = None;
+          }
+
+          self.downloads.cancel(&id);
+        }
+
+        if let Some((id, offset)) = moved {
+          self.downloads.move_by(&id, offset);
+        }
+      });
+    });
+
+    CentralPanel::default().show(ctx, |ui| {
+      let mut selected_tab = None;
+      let mut closed_tab = None;
+      let mut opened_tab = false;
+
+      ui.with_layout(Layout::left_to_right(Align::TOP), |ui| {
+        for (tab_index, tab) in self.tabs.iter().enumerate() {
+          let queued = tab
+            .playlist_videos_info
+            .as_ref()
+            .map_or(0, |playlist_videos_info| {
+              playlist_videos_info
+                .videos
+                .iter()
+                .filter(|video| {
+                  matches!(
+                    self.downloads.state(&video.id),
+                    Some(DownloadState::Queued | DownloadState::Downloading { .. })
+                  )
+                })
+                .count()
+            });
+
+          let mut title = tab.title();
+
+          if queued > 0 {
+            title = format!("{title} (⬇{queued})");
+          }
+
+          if ui
+            .selectable_label(tab_index == self.active_tab, title)
+            .clicked()
+          {
+            selected_tab = Some(tab_index);
+          }
+
+          if self.tabs.len() > 1 && ui.small_button("✖").clicked() {
+            closed_tab = Some(tab_index);
+          }
+        }
+
+        if ui.button("+").on_hover_text("new tab").clicked() {
+          opened_tab = true;
+        }
+      });
+
+      if let Some(tab_index) = selected_tab {
+        self.active_tab = tab_index;
+      }
+
+      if let Some(tab_index) = closed_tab {
+        let mut tab = self.tabs.remove(tab_index);
+        tab.cancel_fetches();
+        release_thumbnails(ctx, &mut tab.loaded_thumbnails, &HashSet::new());
+
+        if self.active_tab > tab_index || self.active_tab == self.tabs.len() {
+          self.active_tab -= 1;
+        }
+      }
+
+      if opened_tab {
+        self.open_tab();
+      }
+
+      ui.separator();
+
+      let tab = &mut self.tabs[self.active_tab];
+      let mut opened_input = false;
+      let mut searched = false;
+
+      ui.with_layout(Layout::left_to_right(Align::TOP), |ui| {
+        let input = ui.add(
+          TextEdit::singleline(&mut tab.input)
+            .hint_text("playlist, channel or video link, @handle or ID"),
+        );
+        let submitted = input.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+
+        opened_input = ui.button("🔍").clicked() || submitted;
+        searched = ui
+          .button("search YouTube")
+          .on_hover_text("search YouTube for what is in the textbox")
+          .clicked();
+
+        if ui.button("⚙").on_hover_text("settings").clicked() {
+          self.settings_draft = Some(SettingsDraft::new(&self.settings, Vec::new()));
+        }
+      });
+
+      if opened_input {
+        self.open_input(self.active_tab, ctx);
+      }
+
+      if searched {
+        self.open_search(self.active_tab, ctx);
+      }
+
+      let mut opened_channel = None;
+      let mut closed_channel = false;
+      let mut opened_query = None;
+      let mut closed_search = false;
+      let mut search_action = None;
+      let mut requested_play = None;
+
+      let tab = &mut self.tabs[self.active_tab];
+
+      // each tab keeps its own scroll position
+      ScrollArea::vertical().id_source(tab.id).show(ui, |ui| {
+        if self.requested_watch_id.is_some() {
+          ui.label("downloading video...");
+        }
+
+        if self.video_player.is_some() && ui.button("back").clicked() {
+          self.current_watching_path = None;
+          self.video_player = None;
+          return;
+        }
+
+        if let Some(video_player) = self.video_player.as_mut() {
+          video_player.ui(ui, video_player.size);
+          return;
+        }
+
+        if let Some(channel_view) = &tab.channel_view {
+          if ui.button("back").clicked() {
+            closed_channel = true;
+          }
+
+          opened_query = channel_view_ui(ui, channel_view);
+          return;
+        }
+
+        if let Some(search_view) = tab.search_view.as_mut() {
+          if ui.button("back").clicked() {
+            closed_search = true;
+          }
+
+          search_action = search_view_ui(ui, search_view);
+          return;
+        }
+
+        if let Some(playlist_info) = &tab.playlist_info {
+          ui.with_layout(Layout::left_to_right(Align::TOP), |ui| {
+            let avatar_width = AVATAR_SIZE.x * ui.ctx().pixels_per_point();
+            thumbnail_ui(ui, playlist_info.channel.avatar(avatar_width), AVATAR_SIZE);
+            ui.with_layout(Layout::top_down(Align::TOP), |ui| {
+              ui.label(RichText::new(&playlist_info.title).size(18.0));
+              ui.with_layout(Layout::left_to_right(Align::TOP), |ui| {
+                ui.label("by");
+
+                if ui
+                  .link(&playlist_info.channel.name)
+                  .on_hover_text("browse the channel's uploads and playlists")
+                  .clicked()
+                {
+                  opened_channel = Some(playlist_info.channel.clone());
+                }
+              });
+
+              if let Some(playlist_videos_info) = &tab.playlist_videos_info {
+                ui.label(playlist_totals(playlist_videos_info));
+              }
+            });
+          });
+        }
+
+        ui.separator();
+
+        if self.connecting && tab.loading_videos {
+          ui.with_layout(Layout::left_to_right(Align::TOP), |ui| {
+            ui.spinner();
+            ui.label("connecting to YouTube...");
+          });
+        } else if tab.loading_videos {
+          ui.with_layout(Layout::left_to_right(Align::TOP), |ui| {
+            ui.spinner();
+
+            let (fetched_videos_info, status) = if tab.showing_cached_playlist {
+              (&tab.refreshed_videos_info, "refreshing")
+            } else {
+              (&tab.playlist_videos_info, "loading videos")
+            };
+
+            let loaded = fetched_videos_info
+              .as_ref()
+              .map_or(0, |playlist_videos_info| playlist_videos_info.videos.len());
+
+            match fetched_videos_info
+              .as_ref()
+              .and_then(|playlist_videos_info| playlist_videos_info.total_count)
+            {
+              Some(total_count) => ui.label(format!("{status}... {loaded}/{total_count}")),
+              None => ui.label(format!("{status}...")),
+            };
+          });
+        }
+
+        if let Some(playlist_videos_info) = &tab.playlist_videos_info {
+          let mut default_format = self.downloads.default_format();
+
+          ui.with_layout(Layout::right_to_left(Align::TOP), |ui| {
+            let download_all_text = match default_format.kind {
+              MediaKind::Video => "download all videos",
+              MediaKind::Audio => "download all tracks",
+            };
+
+            if ui
+              .add(
+                Button::new(RichText::new(download_all_text).color(Color32::WHITE))
+                  .fill(Rgba::from_rgb(0.0, 0.25, 0.40)),
+              )
+              .clicked()
+            {
+              for PlaylistVideo { id, title, .. } in playlist_videos_info.videos.iter() {
+                if self.catalog.cached_path(id, &default_format).is_none() {
+                  self.downloads.enqueue(DownloadJob {
+                    id: id.clone(),
+                    title: title.clone(),
+                    format: default_format,
+                  });
+                }
+              }
+            }
+
+            let audio_toggled = ui
+              .selectable_value(&mut default_format.kind, MediaKind::Audio, "🎵 audio only")
+              .changed();
+            let video_toggled = ui
+              .selectable_value(&mut default_format.kind, MediaKind::Video, "🎬 video")
+              .changed();
+
+            if audio_toggled || video_toggled {
+              self.settings.downloads.default_format = default_format;
+              self.settings.save();
+              self.downloads.apply_settings(&self.settings.downloads);
+            }
+
+            playlist_progress_ui(ui, &self.catalog, playlist_videos_info, default_format.kind);
+          });
+
+          let shown_videos = tab
+            .filtered_videos
+            .get(
+              &tab.video_filter,
+              &playlist_videos_info.videos,
+              tab.videos_revision,
+              &self.catalog,
+              &self.downloads,
+              default_format.kind,
+            )
+            .iter()
+            .map(|&position| (position, &playlist_videos_info.videos[position]))
+            .collect::<Vec<_>>();
+
+          video_filter_ui(
+            ui,
+            &mut tab.video_filter,
+            shown_videos.len(),
+            playlist_videos_info.videos.len(),
+          );
+
+          match default_format.kind {
+            MediaKind::Video => {
+              let spacing = ui.spacing().item_spacing.x;
+              let columns =
+                (((ui.available_width() + spacing) / (TILE_WIDTH + spacing)) as usize).max(1);
+              let rows = shown_videos.chunks(columns).collect::<Vec<_>>();
+              let thumbnail_width = THUMBNAIL_SIZE.x * ui.ctx().pixels_per_point();
+
+              let shown_rows = virtual_rows(ui, TILE_HEIGHT, rows.len(), |ui, row| {
+                for (_, video) in rows[row] {
+                  ui.allocate_ui_with_layout(
+                    Vec2::new(TILE_WIDTH, TILE_HEIGHT),
+                    Layout::top_down(Align::TOP),
+                    |ui| {
+                      thumbnail_ui(ui, video.thumbnail(thumbnail_width), THUMBNAIL_SIZE);
+
+                      let title = ui.add_sized(
+                        [TILE_WIDTH, 32.0],
+                        Label::new(&video.title).wrap().sense(Sense::hover()),
+                      );
+
+                      if let Some(details) = &video.details {
+                        title.on_hover_ui(|ui| video_details_hover_ui(ui, details));
+                        ui.add_sized(
+                          [TILE_WIDTH, 16.0],
+                          Label::new(RichText::new(video_details_summary(details)).small())
+                            .truncate(),
+                        );
+                      }
+
+                      ui.allocate_ui(Vec2::new(TILE_WIDTH, 18.0), |ui| {
+                        video_download_state_ui(
+                          ui,
+                          &self.downloads,
+                          &self.catalog,
+                          &video.id,
+                          MediaKind::Video,
+                        );
+                      });
+
+                      ui.with_layout(Layout::left_to_right(Align::TOP), |ui| {
+                        if ui.button("watch").clicked() {
+                          requested_play = Some((video.id.clone(), video.title.clone()));
+                        }
+
+                        download_menu_ui(ui, &mut self.downloads, &mut self.format_override, video);
+                      });
+                    },
+                  );
+                }
+              });
+
+              // thumbnails stay loaded while they are a few screens away, in case of scrolling back
+              let kept_rows = shown_rows.start.saturating_sub(THUMBNAIL_KEEP_ROWS)
+                ..(shown_rows.end + THUMBNAIL_KEEP_ROWS).min(rows.len());
+              let kept = rows[kept_rows]
+                .iter()
+                .flat_map(|row| row.iter())
+                .map(|(_, video)| video.thumbnail(thumbnail_width))
+                .collect::<HashSet<_>>();
+
+              release_thumbnails(ui.ctx(), &mut tab.loaded_thumbnails, &kept);
+              tab.loaded_thumbnails.extend(
+                rows[shown_rows]
+                  .iter()
+                  .flat_map(|row| row.iter())
+                  .map(|(_, video)| video.thumbnail(thumbnail_width).to_string()),
+              );
+            }
+            MediaKind::Audio => {
+              release_thumbnails(ui.ctx(), &mut tab.loaded_thumbnails, &HashSet::new());
+
+              let row_height = ui.spacing().interact_size.y;
+
+              virtual_rows(ui, row_height, shown_videos.len(), |ui, row| {
+                let (position, video) = shown_videos[row];
+
+                if ui.small_button("▶").clicked() {
+                  requested_play = Some((video.id.clone(), video.title.clone()));
+                }
+
+                download_menu_ui(ui, &mut self.downloads, &mut self.format_override, video);
+
+                ui.label(RichText::new(format!("{:>3}", position + 1)).monospace());
+
+                let duration = video
+                  .details
+                  .as_ref()
+                  .and_then(|details| details.duration)
+                  .map_or_else(String::new, format_duration);
+                ui.label(RichText::new(format!("{duration:>8}")).monospace());
+
+                let title = ui.add_sized(
+                  [360.0, 18.0],
+                  Label::new(&video.title).truncate().sense(Sense::hover()),
+                );
+
+                if let Some(details) = &video.details {
+                  title.on_hover_ui(|ui| video_details_hover_ui(ui, details));
+                }
+                video_download_state_ui(
+                  ui,
+                  &self.downloads,
+                  &self.catalog,
+                  &video.id,
+                  MediaKind::Audio,
+                );
+              });
+            }
+          }
+        } else {
+          ui.label(
+            "Enter a YouTube playlist, channel or video link in the textbox above and click the \
+             search button, or open a saved playlist from the library",
+          );
+        }
+      });
+
+      if let Some((id, title)) = requested_play {
+        let format = self.downloads.default_format();
+        self.play(id, title, format);
+      }
+
+      if let Some(channel) = opened_channel {
+        self.open_channel(self.active_tab, channel, ctx);
+      }
+
+      let tab = &mut self.tabs[self.active_tab];
+
+      if closed_channel {
+        tab.channel_view = None;
+        tab.channel_fetches.cancel();
+      }
+
+      if closed_search {
+        tab.search_view = None;
+        tab.search_fetches.cancel();
+      }
+
+      if let Some((input, query)) = opened_query {
+        tab.input = input;
+        self.open_query(self.active_tab, query, ctx);
+      }
+
+      match search_action {
+        Some(SearchAction::Search) => {
+          let tab = &mut self.tabs[self.active_tab];
+          tab.search_fetches.restart();
+
+          if let Some(search_view) = tab.search_view.as_mut() {
+            search_view.results.clear();
+            search_view.next_cursor = None;
+          }
+
+          self.fetch_search_results(self.active_tab, ctx);
+        }
+        Some(SearchAction::LoadMore) => self.fetch_search_results(self.active_tab, ctx),
+        Some(SearchAction::Open(result)) => self.open_search_result(self.active_tab, result, ctx),
+        None => {}
+      }
+    });
+  }
+
+  fn on_exit(&mut self, _: Option<&eframe::glow::Context>) {
+    self.catalog.save();
+  }
+}
+
+impl Visualizer {
+  /// Moves the library to `library_dir` in the background, holding downloads until it is done.
+  fn start_library_migration(&mut self, library_dir: PathBuf, ctx: &egui::Context) {
+    if library_dir == self.settings.library_dir {
+      return;
+    }
+
+    self.migrating_library = true;
+    self.resume_downloads_after_migration = !self.downloads.is_paused();
+    self.downloads.pause();
+
+    let cloned_library_migration_emit = self.tasks.emit_library_migration.clone();
+    let cloned_ctx = ctx.clone();
+    let current_library_dir = self.settings.library_dir.clone();
+    let catalog_write = self.catalog.take_write();
+
+    tokio::spawn(async move {
+      // the catalog file is moved along, so it has to be written first
+      if let Some(catalog_write) = catalog_write {
+        _ = catalog_write.await;
+      }
+
+      let migration = tokio::task::spawn_blocking(move || {
+        library::migrate(&current_library_dir, &library_dir)
+          .map(|()| library_dir)
+          .map_err(|error| Error::Library(error.to_string()))
+      })
+      .await
+      .unwrap_or_else(|error| Err(Error::Library(error.to_string())));
+
+      _ = cloned_library_migration_emit.send(migration);
+      cloned_ctx.request_repaint();
+    });
+  }
+
+  /// Opens an empty tab after the others and switches to it.
+  fn open_tab(&mut self) {
+    self.tabs.push(Tab::new(self.next_tab_id));
+    self.next_tab_id += 1;
+    self.active_tab = self.tabs.len() - 1;
+  }
+
+  /// Opens whatever was typed into the tab's search box, or explains why it can't be.
+  fn open_input(&mut self, tab_index: usize, ctx: &egui::Context) {
+    let input = &self.tabs[tab_index].input;
+
+    match input.parse::<Query>() {
+      Ok(query) => self.open_query(tab_index, query, ctx),
+      Err(reason) => self.notify(Error::Input {
+        input: input.trim().to_string(),
+        reason,
+      }),
+    }
+  }
+
+  fn open_query(&mut self, tab_index: usize, query: Query, ctx: &egui::Context) {
+    let tab = &mut self.tabs[tab_index];
+    tab.channel_view = None;
+    tab.search_view = None;
+    tab.query = Some(query);
+    tab.show_cached_playlist(&self.catalog);
+
+    self.refresh_playlist(tab_index, ctx);
+  }
+
+  fn open_channel(&mut self, tab_index: usize, channel: YouTubeChannel, ctx: &egui::Context) {
+    let tab = &mut self.tabs[tab_index];
+    tab.channel_fetches.restart();
+    tab.channel_view = Some(ChannelView {
+      channel,
+      playlists: Vec::new(),
+      loading: true,
+    });
+
+    self.fetch_channel_playlists(tab_index, ctx);
+  }
+
+  /// Searches YouTube for what is in the tab's search box, keeping the kind of results last
+  /// searched for.
+  fn open_search(&mut self, tab_index: usize, ctx: &egui::Context) {
+    let tab = &mut self.tabs[tab_index];
+    let terms = tab.input.trim().to_string();
+
+    if terms.is_empty() {
+      return;
+    }
+
+    let kind = tab
+      .search_view
+      .as_ref()
+      .map_or_else(SearchKind::default, |search_view| search_view.kind);
+
+    tab.channel_view = None;
+    tab.search_fetches.restart();
+    tab.search_view = Some(SearchView {
+      terms,
+      kind,
+      results: Vec::new(),
+      next_cursor: None,
+      loading: false,
+    });
+
+    self.fetch_search_results(tab_index, ctx);
+  }
+
+  /// Fetches the next page of search results, or the first when there are none yet, connecting
+  /// first if needed.
+  fn fetch_search_results(&mut self, tab_index: usize, ctx: &egui::Context) {
+    let tab = &mut self.tabs[tab_index];
+    let fetch_id = FetchId {
+      tab_id: tab.id,
+      generation: tab.search_fetches.generation,
+    };
+
+    let Some(search_view) = tab.search_view.as_mut() else {
+      return;
+    };
+
+    search_view.loading = true;
+
+    let cloned_terms = search_view.terms.clone();
+    let kind = search_view.kind;
+    let cursor = search_view.next_cursor.clone();
+
+    let Some(yt_client) = &self.yt_client else {
+      self.connect(ctx);
+      return;
+    };
+
+    let cloned_search_results_emit = self.tasks.emit_search_results.clone();
+    let cloned_yt_client = yt_client.clone();
+    let cloned_ctx = ctx.clone();
+
+    let fetch = tokio::spawn(async move {
+      let search_results = youtube::search(cloned_yt_client, &cloned_terms, kind, cursor).await;
+
+      _ = cloned_search_results_emit.send((fetch_id, search_results));
+      cloned_ctx.request_repaint();
+    });
+
+    self.tabs[tab_index]
+      .search_fetches
+      .track(fetch.abort_handle());
+  }
+
+  /// Opens a video to watch, a playlist into the grid, or a channel into the channel view.
+  fn open_search_result(&mut self, tab_index: usize, result: SearchResult, ctx: &egui::Context) {
+    match result.query {
+      Query::Video(id) => {
+        let format = self.downloads.default_format();
+        self.play(id, result.title, format);
+      }
+      Query::Channel(ChannelRef::Id(id)) => self.open_channel(
+        tab_index,
+        YouTubeChannel {
+          id,
+          name: result.title,
+          avatar_url: result.thumbnails.smallest_url(),
+          avatars: result.thumbnails,
+          uploads_playlist_id: None,
+        },
+        ctx,
+      ),
+      query => {
+        if let Some(playlist_id) = query.playlist_id() {
+          self.tabs[tab_index].input = playlist_id;
+        }
+
+        self.open_query(tab_index, query, ctx);
+      }
+    }
+  }
+
+  /// Fetches the playlists of the channel open in the tab, connecting first if needed.
+  fn fetch_channel_playlists(&mut self, tab_index: usize, ctx: &egui::Context) {
+    let tab = &self.tabs[tab_index];
+    let fetch_id = FetchId {
+      tab_id: tab.id,
+      generation: tab.channel_fetches.generation,
+    };
+
+    let Some(channel_view) = &tab.channel_view else {
+      return;
+    };
+
+    let cloned_channel_id = channel_view.channel.id.clone();
+
+    let Some(yt_client) = &self.yt_client else {
+      self.connect(ctx);
+      return;
+    };
+
+    let cloned_channel_playlists_emit = self.tasks.emit_channel_playlists.clone();
+    let cloned_yt_client = yt_client.clone();
+    let cloned_ctx = ctx.clone();
+
+    let fetch = tokio::spawn(async move {
+      let fetched =
+        youtube::fetch_all_channel_playlists(cloned_yt_client, &cloned_channel_id, |page| {
+          _ = cloned_channel_playlists_emit.send((fetch_id, Ok(page)));
+          cloned_ctx.request_repaint();
+        })
+        .await;
+
+      if let Err(error) = fetched {
+        _ = cloned_channel_playlists_emit.send((fetch_id, Err(error)));
+        cloned_ctx.request_repaint();
+      }
+    });
+
+    self.tabs[tab_index]
+      .channel_fetches
+      .track(fetch.abort_handle());
+  }
+
+  /// Connects to YouTube in the background, signing in unless an API key is configured.
+  fn connect(&mut self, ctx: &egui::Context) {
+    if self.connecting {
+      return;
+    }
+
+    self.connecting = true;
+
+    let cloned_yt_emit = self.tasks.emit_yt_client.clone();
+    let cloned_ctx = ctx.clone();
+
+    let youtube_settings = self.settings.youtube.clone();
+
+    tokio::spawn(async move {
+      _ = cloned_yt_emit.send(youtube::fetch_youtube_client(youtube_settings).await);
+      cloned_ctx.request_repaint();
+    });
+  }
+
+  fn settings_window_ui(&mut self, ctx: &egui::Context) {
+    let Some(draft) = self.settings_draft.as_mut() else {
+      return;
+    };
+
+    let mut open = true;
+    let mut saved = false;
+
+    Window::new("Settings")
+      .open(&mut open)
+      .collapsible(false)
+      .show(ctx, |ui| {
+        Grid::new("general_settings").num_columns(2).show(ui, |ui| {
+          ui.label("library folder");
+          ui.with_layout(Layout::left_to_right(Align::TOP), |ui| {
+            ui.text_edit_singleline(&mut draft.library_dir);
+
+            if ui.button("browse…").clicked() {
+              if let Some(library_dir) = rfd::FileDialog::new()
+                .set_directory(&draft.library_dir)
+                .pick_folder()
+              {
+                draft.library_dir = library_dir.to_string_lossy().into_owned();
+              }
+            }
+          });
+          ui.end_row();
+
+          ui.label("theme");
+          ComboBox::from_id_source("theme")
+            .selected_text(draft.settings.theme.to_string())
+            .show_ui(ui, |ui| {
+              for theme in [Theme::Dark, Theme::Light] {
+                ui.selectable_value(&mut draft.settings.theme, theme, theme.to_string());
+              }
+            });
+          ui.end_row();
+
+          ui.label("default format");
+          format_picker_ui(
+            ui,
+            "settings_format",
+            &mut draft.settings.downloads.default_format,
+          );
+          ui.end_row();
+
+          ui.label("downloads at once");
+          ui.add(
+            DragValue::new(&mut draft.settings.downloads.max_concurrent)
+              .range(1..=MAX_CONCURRENT_LIMIT),
+          );
+          ui.end_row();
+        });
+
+        ui.separator();
+
+        ui.heading("YouTube");
+        ui.label("An API key is enough for public playlists; an OAuth client signs you in.");
+
+        let youtube = &mut draft.settings.youtube;
+
+        Grid::new("youtube_settings").num_columns(2).show(ui, |ui| {
+          ui.label("API key");
+          ui.add(TextEdit::singleline(&mut youtube.api_key).password(true));
+          ui.end_row();
+
+          ui.label("client ID");
+          ui.text_edit_singleline(&mut youtube.client_id);
+          ui.end_row();
+
+          ui.label("client secret");
+          ui.add(TextEdit::singleline(&mut youtube.client_secret).password(true));
+          ui.end_row();
+
+          ui.label("auth URI");
+          ui.text_edit_singleline(&mut youtube.auth_uri);
+          ui.end_row();
+
+          ui.label("token URI");
+          ui.text_edit_singleline(&mut youtube.token_uri);
+          ui.end_row();
+
+          ui.label("redirect port");
+          ui.add(DragValue::new(&mut youtube.redirect_port).range(1024..=65535));
+          ui.end_row();
+        });
+
+        ui.separator();
+
+        for problem in draft.problems.iter() {
+          ui.label(RichText::new(problem).color(Color32::RED));
+        }
+
+        if ui.button("save").clicked() {
+          saved = true;
+        }
+      });
+
+    if !open {
+      self.settings_draft = None;
+      return;
+    }
+
+    if !saved {
+      return;
+    }
+
+    let mut settings = draft.settings.clone();
+    settings.library_dir = PathBuf::from(draft.library_dir.trim());
+
+    draft.problems = settings.problems();
+
+    if !draft.problems.is_empty() {
+      return;
+    }
+
+    self.downloads.apply_settings(&settings.downloads);
+    ctx.set_visuals(settings.theme.visuals());
+
+    // connect again with the new credentials when they are next needed
+    if settings.youtube != self.settings.youtube {
+      self.yt_client = None;
+    }
+
+    // the library directory only changes once the library has been moved there
+    let library_dir =
+      std::mem::replace(&mut settings.library_dir, self.settings.library_dir.clone());
+    self.settings = settings;
+    self.settings.save();
+    self.settings_draft = None;
+
+    self.start_library_migration(library_dir, ctx);
+  }
+
+  /// Saves a change made to the download settings outside of the settings window.
+  fn save_download_settings(&mut self) {
+    self.settings.save();
+    self.downloads.apply_settings(&self.settings.downloads);
+  }
+
+  /// Drops the connection and the stored account, signing in again afterwards when switching.
+  fn sign_out(&mut self, ctx: &egui::Context, sign_in_again: bool) {
+    self.yt_client = None;
+    self.connecting = sign_in_again;
+
+    let cloned_yt_emit = self.tasks.emit_yt_client.clone();
+    let cloned_ctx = ctx.clone();
+    let youtube_settings = self.settings.youtube.clone();
+
+    tokio::spawn(async move {
+      youtube::sign_out().await;
+
+      if sign_in_again {
+        _ = cloned_yt_emit.send(youtube::fetch_youtube_client(youtube_settings).await);
+        cloned_ctx.request_repaint();
+      }
+    });
+  }
+
+  /// Fetches what the tab's query refers to, connecting first if needed.
+  fn refresh_playlist(&mut self, tab_index: usize, ctx: &egui::Context) {
+    let tab = &mut self.tabs[tab_index];
+
+    let Some(query) = tab.query.clone() else {
+      return;
+    };
+
+    tab.loading_videos = true;
+
+    let fetch_id = FetchId {
+      tab_id: tab.id,
+      generation: tab.listing_fetches.restart(),
+    };
+
+    let Some(yt_client) = &self.yt_client else {
+      tab.fetch_after_connect = true;
+      self.connect(ctx);
+      return;
+    };
+
+    let cloned_playlist_info_emit = self.tasks.emit_playlist_info.clone();
+    let cloned_playlist_videos_info_emit = self.tasks.emit_playlist_videos_info.clone();
+    let cloned_yt_client = yt_client.clone();
+    let cloned_ctx = ctx.clone();
+
+    let fetch = tokio::spawn(async move {
+      let fetched = match youtube::fetch_listing(cloned_yt_client.clone(), &query).await {
+        Ok(Listing::Playlist(playlist_info)) => {
+          let playlist_id = playlist_info.id.clone();
+          _ = cloned_playlist_info_emit.send((fetch_id, playlist_info));
+          cloned_ctx.request_repaint();
+
+          youtube::fetch_all_video_pages(cloned_yt_client, &playlist_id, |page| {
+            _ = cloned_playlist_videos_info_emit.send((fetch_id, Ok(page)));
+            cloned_ctx.request_repaint();
+          })
+          .await
+        }
+        Ok(Listing::Video(playlist_info, playlist_videos_info)) => {
+          _ = cloned_playlist_info_emit.send((fetch_id, playlist_info));
+          _ = cloned_playlist_videos_info_emit.send((fetch_id, Ok(playlist_videos_info)));
+          cloned_ctx.request_repaint();
+
+          Ok(())
+        }
+        Err(error) => Err(error),
+      };
+
+      // also stops the progress indicator instead of waiting on a page that never arrives
+      if let Err(error) = fetched {
+        _ = cloned_playlist_videos_info_emit.send((fetch_id, Err(error)));
+        cloned_ctx.request_repaint();
+      }
+    });
+
+    self.tabs[tab_index]
+      .listing_fetches
+      .track(fetch.abort_handle());
+  }
+
+  /// Shows `error` until it is dismissed, unless the same one is already showing.
+  fn notify(&mut self, error: Error) {
+    let summary = error.to_string();
+
+    // errors of one kind share a summary, so only the details tell them apart
+    if self.notifications.iter().any(|notification| {
+      notification.error.to_string() == summary && notification.error.details() == error.details()
+    }) {
+      return;
+    }
+
+    self.notifications.push(Notification {
+      id: self.next_notification_id,
+      error,
+    });
+    self.next_notification_id += 1;
+  }
+
+  /// Stacks notifications in the bottom right corner until they are dismissed.
+  fn notifications_ui(&mut self, ctx: &egui::Context) {
+    if self.notifications.is_empty() {
+      return;
+    }
+
+    let mut dismissed_id = None;
+
+    Area::new("notifications".into())
+      .anchor(Align2::RIGHT_BOTTOM, Vec2::new(-8.0, -8.0))
+      .show(ctx, |ui| {
+        ui.set_max_width(320.0);
+
+        for notification in self.notifications.iter() {
+          Frame::popup(ui.style()).show(ui, |ui| {
+            ui.push_id(notification.id, |ui| {
+              ui.with_layout(Layout::left_to_right(Align::TOP), |ui| {
+                if ui.small_button("✖").clicked() {
+                  dismissed_id = Some(notification.id);
+                }
+
+                ui.add(
+                  Label::new(RichText::new(notification.error.to_string()).color(Color32::RED))
+                    .wrap(),
+                );
+              });
+
+              ui.collapsing("details", |ui| {
+                ui.add(Label::new(notification.error.details()).wrap());
+              });
+            });
+          });
+        }
+      });
+
+    if let Some(id) = dismissed_id {
+      self
+        .notifications
+        .retain(|notification| notification.id != id);
+    }
+  }
+
+  /// Plays `id` from the library, downloading it first if there is no file in `format` yet.
+  fn play(&mut self, id: String, title: String, format: FormatPreference) {
+    if let Some(path) = self.catalog.cached_path(&id, &format) {
+      _ = self.tasks.emit_downloaded_path.send((path, format.kind));
+    } else {
+      self.requested_watch_id = Some(id.clone());
+      self
+        .downloads
+        .enqueue_front(DownloadJob { id, title, format });
+    }
+  }
+}
+
+/// The tab that started `fetch_id`, unless it has started over with a new generation since.
+fn fetching_tab(
+  tabs: &mut [Tab],
+  fetch_id: FetchId,
+  fetches: impl Fn(&Tab) -> &Fetches,
+) -> Option<&mut Tab> {
+  tabs
+    .iter_mut()
+    .find(|tab| tab.id == fetch_id.tab_id && fetches(tab).generation == fetch_id.generation)
+}
+
+/// `1:02:03`, or `2:03` under an hour.
+fn format_duration(seconds: u64) -> String {
+  let (hours, minutes, seconds) = (seconds / 3600, seconds / 60 % 60, seconds % 60);
+
+  if hours > 0 {
+    format!("{hours}:{minutes:02}:{seconds:02}")
+  } else {
+    format!("{minutes}:{seconds:02}")
+  }
+}
+
+/// `950`, `12K`, `1.2M`, as view counts are usually shown.
+fn format_count(count: u64) -> String {
+  const UNITS: [(u64, &str); 3] = [(1_000_000_000, "B"), (1_000_000, "M"), (1_000, "K")];
+
+  for (size, unit) in UNITS {
+    if count >= size {
+      let value = count as f64 / size as f64;
+
+      return if value < 10.0 {
+        format!("{value:.1}{unit}")
+      } else {
+        format!("{value:.0}{unit}")
+      };
+    }
+  }
+
+  count.to_string()
+}
+
+/// Duration, views and publish date, as fits under a thumbnail.
+fn video_details_summary(details: &VideoDetails) -> String {
+  [
+    details.duration.map(format_duration),
+    details
+      .view_count
+      .map(|view_count| format!("{} views", format_count(view_count))),
+    details.published.clone(),
+  ]
+  .into_iter()
+  .flatten()
+  .collect::<Vec<_>>()
+  .join(" · ")
+}
+
+fn video_details_hover_ui(ui: &mut Ui, details: &VideoDetails) {
+  ui.set_max_width(360.0);
+
+  if let Some(channel_name) = &details.channel_name {
+    ui.label(RichText::new(channel_name).strong());
+  }
+
+  ui.label(video_details_summary(details));
+
+  if let Some(like_count) = details.like_count {
+    ui.label(format!("{} likes", format_count(like_count)));
+  }
+
+  if !details.description.is_empty() {
+    ui.separator();
+
+    // descriptions can run for pages
+    let description = match details.description.char_indices().nth(500) {
+      Some((end, _)) => format!("{}…", &details.description[..end]),
+      None => details.description.clone(),
+    };
+
+    ui.add(Label::new(description).wrap());
+  }
+}
+
+/// Video count, overall runtime and views of the videos fetched so far.
+fn playlist_totals(playlist_videos_info: &PlaylistVideos) -> String {
+  let videos = &playlist_videos_info.videos;
+  let details = videos.iter().filter_map(|video| video.details.as_ref());
+
+  let runtime = details
+    .clone()
+    .filter_map(|details| details.duration)
+    .sum::<u64>();
+  let views = details
+    .filter_map(|details| details.view_count)
+    .sum::<u64>();
+
+  let mut totals = vec![format!("{} videos", videos.len())];
+
+  if runtime > 0 {
+    totals.push(format!("{} total", format_duration(runtime)));
+  }
+
+  if views > 0 {
+    totals.push(format!("{} views", format_count(views)));
+  }
+
+  totals.join(" · ")
+}
+
+fn video_download_state_ui(
+  ui: &mut Ui,
+  downloads: &DownloadManager,
+  catalog: &Catalog,
+  id: &str,
+  kind: MediaKind,
+) {
+  if let Some(state) = downloads.state(id) {
+    download_state_ui(ui, &state);
+  } else if let Some(file) = catalog.file(id, kind) {
+    ui.label(format!(
+      "downloaded ({} · {})",
+      file
+        .format
+        .quality_label
+        .as_deref()
+        .unwrap_or(&file.format.container),
+      format_bytes(file.size)
+    ));
+  }
+}
+
+/// Lists the channel's uploads and playlists, returning the one that was opened along with the
+/// text to put in the search box for it.
+fn channel_view_ui(ui: &mut Ui, channel_view: &ChannelView) -> Option<(String, Query)> {
+  let channel = &channel_view.channel;
+  let mut opened = None;
+
+  ui.with_layout(Layout::left_to_right(Align::TOP), |ui| {
+    let avatar_width = AVATAR_SIZE.x * ui.ctx().pixels_per_point();
+    thumbnail_ui(ui, channel.avatar(avatar_width), AVATAR_SIZE);
+    ui.with_layout(Layout::top_down(Align::TOP), |ui| {
+      ui.label(RichText::new(&channel.name).size(18.0));
+      ui.hyperlink_to(
+        "open on YouTube",
+        format!("https://youtube.com/channel/{}", &channel.id),
+      );
+    });
+  });
+
+  ui.separator();
+
+  if ui.button("▶ uploads").clicked() {
+    opened = Some(match &channel.uploads_playlist_id {
+      Some(playlist_id) => (playlist_id.clone(), Query::Playlist(playlist_id.clone())),
+      None => (
+        channel.id.clone(),
+        Query::Channel(ChannelRef::Id(channel.id.clone())),
+      ),
+    });
+  }
+
+  if channel_view.loading {
+    ui.with_layout(Layout::left_to_right(Align::TOP), |ui| {
+      ui.spinner();
+      ui.label(format!(
+        "loading playlists... {}",
+        channel_view.playlists.len()
+      ));
+    });
+  } else if channel_view.playlists.is_empty() {
+    ui.label("this channel has no public playlists");
+  }
+
+  let thumbnail_width = THUMBNAIL_SIZE.x * ui.ctx().pixels_per_point();
+
+  ui.with_layout(
+    Layout::left_to_right(Align::TOP).with_main_wrap(true),
+    |ui| {
+      for playlist in channel_view.playlists.iter() {
+        ui.with_layout(Layout::top_down(Align::TOP).with_main_wrap(true), |ui| {
+          let thumbnail = playlist
+            .thumbnails
+            .best(thumbnail_width)
+            .unwrap_or_default();
+          thumbnail_ui(ui, thumbnail, THUMBNAIL_SIZE);
+
+          ui.add_sized([200.0, 32.0], Label::new(&playlist.title).wrap());
+
+          ui.with_layout(Layout::left_to_right(Align::TOP), |ui| {
+            if ui.button("open").clicked() {
+              opened = Some((playlist.id.clone(), Query::Playlist(playlist.id.clone())));
+            }
+
+            if let Some(video_count) = playlist.video_count {
+              ui.label(format!("{video_count} videos"));
+            }
+          });
+        });
+      }
+    },
+  );
+
+  opened
+}
+
+fn search_view_ui(ui: &mut Ui, search_view: &mut SearchView) -> Option<SearchAction> {
+  let mut action = None;
+
+  ui.with_layout(Layout::left_to_right(Align::TOP), |ui| {
+    ui.label(RichText::new(format!("results for \"{}\"", search_view.terms)).size(18.0));
+
+    for kind in [SearchKind::Video, SearchKind::Playlist, SearchKind::Channel] {
+      if ui
+        .selectable_value(&mut search_view.kind, kind, kind.to_string())
+        .changed()
+      {
+        action = Some(SearchAction::Search);
+      }
+    }
+  });
+
+  ui.separator();
+
+  let thumbnail_width = THUMBNAIL_SIZE.x * ui.ctx().pixels_per_point();
+
+  ui.with_layout(
+    Layout::left_to_right(Align::TOP).with_main_wrap(true),
+    |ui| {
+      for result in search_view.results.iter() {
+        ui.with_layout(Layout::top_down(Align::TOP).with_main_wrap(true), |ui| {
+          let thumbnail = result.thumbnails.best(thumbnail_width).unwrap_or_default();
+          thumbnail_ui(ui, thumbnail, THUMBNAIL_SIZE);
+
+          ui.add_sized([200.0, 32.0], Label::new(&result.title).wrap());
+
+          ui.with_layout(Layout::left_to_right(Align::TOP), |ui| {
+            let open_text = match result.query {
+              Query::Video(_) => "watch",
+              _ => "open",
+            };
+
+            if ui.button(open_text).clicked() {
+              action = Some(SearchAction::Open(result.clone()));
+            }
+
+            if !matches!(result.query, Query::Channel(_)) {
+              ui.add(Label::new(&result.channel_name).truncate());
+            }
+          });
+        });
+      }
+    },
+  );
+
+  if search_view.loading {
+    ui.with_layout(Layout::left_to_right(Align::TOP), |ui| {
+      ui.spinner();
+      ui.label("searching...");
+    });
+  } else if search_view.results.is_empty() {
+    ui.label("nothing found");
+  } else if search_view.next_cursor.is_some() && ui.button("more results").clicked() {
+    action = Some(SearchAction::LoadMore);
+  }
+
+  action
+}
+
+/// Sort order, filters and text search for the playlist's videos; `shown` of `total` pass.
+fn video_filter_ui(ui: &mut Ui, filter: &mut VideoFilter, shown: usize, total: usize) {
+  ui.with_layout(
+    Layout::left_to_right(Align::Center).with_main_wrap(true),
+    |ui| {
+      ui.add(
+        TextEdit::singleline(&mut filter.text)
+          .hint_text("filter titles and descriptions")
+          .desired_width(200.0),
+      );
+
+      ui.label("sort by");
+      ComboBox::from_id_source("sort_by")
+        .selected_text(filter.sort_by.to_string())
+        .show_ui(ui, |ui| {
+          for sort_by in [
+            SortBy::Position,
+            SortBy::Title,
+            SortBy::Published,
+            SortBy::Duration,
+            SortBy::DownloadState,
+          ] {
+            ui.selectable_value(&mut filter.sort_by, sort_by, sort_by.to_string());
+          }
+        });
+
+      let direction = if filter.descending { "⬇" } else { "⬆" };
+      if ui
+        .small_button(direction)
+        .on_hover_text("reverse the order")
+        .clicked()
+      {
+        filter.descending = !filter.descending;
+      }
+
+      let labels = |value: Option<bool>, yes: &'static str, no: &'static str| match value {
+        None => "all",
+        Some(true) => yes,
+        Some(false) => no,
+      };
+
+      ComboBox::from_id_source("downloaded_filter")
+        .selected_text(labels(filter.downloaded, "downloaded", "not downloaded"))
+        .show_ui(ui, |ui| {
+          for value in [None, Some(true), Some(false)] {
+            let label = labels(value, "downloaded", "not downloaded");
+            ui.selectable_value(&mut filter.downloaded, value, label);
+          }
+        });
+
+      ComboBox::from_id_source("watched_filter")
+        .selected_text(labels(filter.watched, "watched", "unwatched"))
+        .show_ui(ui, |ui| {
+          for value in [None, Some(true), Some(false)] {
+            let label = labels(value, "watched", "unwatched");
+            ui.selectable_value(&mut filter.watched, value, label);
+          }
+        });
+
+      ui.label("minutes");
+      ui.add(
+        DragValue::new(&mut filter.min_minutes).custom_formatter(|minutes, _| {
+          match minutes as u32 {
+            0 => "any".into(),
+            minutes => minutes.to_string(),
+          }
+        }),
+      )
+      .on_hover_text("shortest length shown");
+      ui.label("to");
+      ui.add(
+        DragValue::new(&mut filter.max_minutes).custom_formatter(|minutes, _| {
+          match minutes as u32 {
+            0 => "any".into(),
+            minutes => minutes.to_string(),
+          }
+        }),
+      )
+      .on_hover_text("longest length shown");
+
+      if filter.is_active() {
+        ui.label(format!("{shown} of {total} shown"));
+
+        if ui.small_button("clear filters").clicked() {
+          *filter = VideoFilter {
+            sort_by: filter.sort_by,
+            descending: filter.descending,
+            ..Default::default()
+          };
+        }
+      }
+    },
+  );
+}
+
+/// Shows the image at `uri` fitted into `size`, leaving the space blank if there is none.
+fn thumbnail_ui(ui: &mut Ui, uri: &str, size: Vec2) {
+  if uri.is_empty() {
+    ui.allocate_space(size);
+  } else {
+    ui.add(Image::from_uri(uri).fit_to_exact_size(size));
+  }
+}
+
+/// Lays out only the rows of a `row_count` long list that are scrolled into view, leaving
+/// empty space in place of the others, and returns which rows were laid out.
+fn virtual_rows(
+  ui: &mut Ui,
+  row_height: f32,
+  row_count: usize,
+  mut add_row: impl FnMut(&mut Ui, usize),
+) -> Range<usize> {
+  let row_stride = row_height + ui.spacing().item_spacing.y;
+  let top = ui.cursor().top();
+  let visible = ui.clip_rect();
+
+  let end = (((visible.bottom() - top) / row_stride).ceil().max(0.0) as usize).min(row_count);
+  let start = (((visible.top() - top) / row_stride).floor().max(0.0) as usize).min(end);
+
+  ui.add_space(start as f32 * row_stride);
+
+  for row in start..end {
+    ui.allocate_ui_with_layout(
+      Vec2::new(ui.available_width(), row_height),
+      Layout::left_to_right(Align::Center),
+      |ui| {
+        ui.set_height(row_height);
+        add_row(ui, row);
+      },
+    );
+  }
+
+  ui.add_space((row_count - end) as f32 * row_stride);
+
+  start..end
+}
+
+/// Frees the loaded `thumbnails` that aren't in `kept`, so they are fetched again if needed.
+fn release_thumbnails(ctx: &egui::Context, thumbnails: &mut HashSet<String>, kept: &HashSet<&str>) {
+  thumbnails.retain(|uri| {
+    let keep = kept.contains(uri.as_str());
+
+    if !keep {
+      ctx.forget_image(uri);
+    }
+
+    keep
+  });
+}
+
+/// How much of the playlist is in the library as `kind`.
+fn playlist_progress_ui(
+  ui: &mut Ui,
+  catalog: &Catalog,
+  playlist_videos_info: &PlaylistVideos,
+  kind: MediaKind,
+) {
+  let total = playlist_videos_info.videos.len();
+
+  if total == 0 {
+    return;
+  }
+
+  let downloaded = playlist_videos_info
+    .videos
+    .iter()
+    .filter(|video| catalog.file(&video.id, kind).is_some())
+    .count();
+
+  ui.add(
+    ProgressBar::new(downloaded as f32 / total as f32)
+      .desired_width(160.0)
+      .text(format!("{downloaded}/{total} downloaded")),
+  );
+}
+
+fn download_menu_ui(
+  ui: &mut Ui,
+  downloads: &mut DownloadManager,
+  format_override: &mut FormatPreference,
+  video: &PlaylistVideo,
+) {
+  let default_format = downloads.default_format();
+
+  let response = ui
+    .menu_button("⬇", |ui| {
+      format_picker_ui(ui, &video.id, format_override);
+
+      if ui.button("download").clicked() {
+        downloads.enqueue(DownloadJob {
+          id: video.id.clone(),
+          title: video.title.clone(),
+          format: *format_override,
+        });
+        ui.close_menu();
+      }
+    })
+    .response
+    .on_hover_text("download in a specific format");
+
+  // every menu starts out at the default rather than what was picked for another video
+  if response.clicked() {
+    *format_override = default_format;
+  }
+}
+
+fn download_state_ui(ui: &mut Ui, state: &DownloadState) {
+  match state {
+    DownloadState::Queued => {
+      ui.label("queued");
+    }
+    DownloadState::Downloading {
+      downloaded,
+      total,
+      bytes_per_second,
+    } => {
+      let speed = format!("{}/s", format_bytes(*bytes_per_second));
+
+      if *total > 0 {
+        let fraction = *downloaded as f32 / *total as f32;
+
+        ui.add(
+          ProgressBar::new(fraction)
+            .desired_width(200.0)
+            .text(format!("{:.0}% · {speed}", fraction * 100.0)),
+        );
+      } else {
+        ui.label(format!(
+          "downloading... {} · {speed}",
+          format_bytes(*downloaded)
+        ));
+      }
+    }
+    DownloadState::Done => {
+      ui.label(RichText::new("✔ downloaded").color(Color32::GREEN));
+    }
+    DownloadState::Failed(reason) => {
+      ui.label(RichText::new("failed").color(Color32::RED))
+        .on_hover_text(reason);
+    }
+  }
+}
+
+/// Kind, quality and container pickers for `format`, returning whether any of them changed.
+fn format_picker_ui(ui: &mut Ui, id_source: &str, format: &mut FormatPreference) -> bool {
+  let before = *format;
+
+  ui.with_layout(Layout::left_to_right(Align::TOP), |ui| {
+    ComboBox::from_id_source(("kind", id_source))
+      .selected_text(format.kind.to_string())
+      .show_ui(ui, |ui| {
+        for kind in [MediaKind::Video, MediaKind::Audio] {
+          ui.selectable_value(&mut format.kind, kind, kind.to_string());
+        }
+      });
+
+    ComboBox::from_id_source(("quality", id_source))
+      .selected_text(format.quality.to_string())
+      .show_ui(ui, |ui| {
+        ui.selectable_value(&mut format.quality, Quality::Highest, "highest");
+        ui.selectable_value(&mut format.quality, Quality::Lowest, "lowest");
+
+        for resolution in RESOLUTIONS {
+          let quality = Quality::Resolution(resolution);
+          ui.selectable_value(&mut format.quality, quality, quality.to_string());
+        }
+      });
+
+    ComboBox::from_id_source(("container", id_source))
+      .selected_text(format.container.to_string())
+      .show_ui(ui, |ui| {
+        for container in [Container::Any, Container::Mp4, Container::Webm] {
+          ui.selectable_value(&mut format.container, container, container.to_string());
+        }
+      });
+  });
+
+  *format != before
 }
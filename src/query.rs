@@ -0,0 +1,340 @@
+use std::str::FromStr;
+use url::Url;
+
+/// A channel as it can appear in a link.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ChannelRef {
+  Id(String),
+  /// Without the leading `@`.
+  Handle(String),
+  /// Legacy `/user/` name.
+  Username(String),
+}
+
+impl ChannelRef {
+  /// The playlist YouTube keeps the channel's uploads in, when it can be told without asking.
+  pub fn uploads_playlist_id(&self) -> Option<String> {
+    match self {
+      Self::Id(id) => id.strip_prefix("UC").map(|id| format!("UU{id}")),
+      _ => None,
+    }
+  }
+}
+
+/// What was typed or pasted into the search box.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Query {
+  Playlist(String),
+  /// The channel's uploads.
+  Channel(ChannelRef),
+  Video(String),
+}
+
+impl Query {
+  /// The playlist the catalog would have saved this as.
+  pub fn playlist_id(&self) -> Option<String> {
+    match self {
+      Self::Playlist(id) => Some(id.clone()),
+      Self::Channel(channel) => channel.uploads_playlist_id(),
+      Self::Video(_) => None,
+    }
+  }
+}
+
+fn is_id(id: &str) -> bool {
+  !id.is_empty()
+    && id
+      .chars()
+      .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+fn id(id: &str, what: &str) -> Result<String, String> {
+  if is_id(id) {
+    Ok(id.to_string())
+  } else {
+    Err(format!("`{id}` isn't a valid {what} ID"))
+  }
+}
+
+fn handle(handle: &str) -> Result<ChannelRef, String> {
+  let handle = handle.trim_start_matches('@');
+
+  if !handle.is_empty()
+    && handle
+      .chars()
+      .all(|c| c.is_alphanumeric() || matches!(c, '-' | '_' | '.'))
+  {
+    Ok(ChannelRef::Handle(handle.to_string()))
+  } else {
+    Err(format!("`@{handle}` isn't a valid channel handle"))
+  }
+}
+
+/// A bare ID, told apart by its shape: channel IDs start with `UC` and are 24 characters,
+/// video IDs are 11 characters, and anything else is taken to be a playlist.
+fn parse_id(input: &str) -> Result<Query, String> {
+  if !is_id(input) {
+    return Err("expected a YouTube link, an @handle, or a playlist, channel or video ID".into());
+  }
+
+  Ok(match input.len() {
+    24 if input.starts_with("UC") => Query::Channel(ChannelRef::Id(input.to_string())),
+    11 => Query::Video(input.to_string()),
+    _ => Query::Playlist(input.to_string()),
+  })
+}
+
+fn parse_url(url: &Url) -> Result<Query, String> {
+  let host = url.host_str().unwrap_or_default();
+  let host = ["www.", "m.", "music."]
+    .iter()
+    .find_map(|prefix| host.strip_prefix(prefix))
+    .unwrap_or(host);
+
+  let param = |name: &str| {
+    url
+      .query_pairs()
+      .find(|(key, _)| key == name)
+      .map(|(_, value)| value.into_owned())
+  };
+
+  let segments = url
+    .path_segments()
+    .map(|segments| {
+      segments
+        .filter(|segment| !segment.is_empty())
+        .collect::<Vec<_>>()
+    })
+    .unwrap_or_else(Vec::new);
+
+  // a video opened from a playlist stands for the playlist
+  if let Some(list) = param("list") {
+    return id(&list, "playlist").map(Query::Playlist);
+  }
+
+  match (host, segments.as_slice()) {
+    ("youtu.be", [video_id, ..]) => id(video_id, "video").map(Query::Video),
+    ("youtube.com" | "youtube-nocookie.com", segments) => match segments {
+      ["watch"] => param("v")
+        .ok_or_else(|| "the link has no video ID".to_string())
+        .and_then(|video_id| id(&video_id, "video"))
+        .map(Query::Video),
+      ["shorts" | "live" | "embed" | "v", video_id, ..] => id(video_id, "video").map(Query::Video),
+      ["playlist"] => Err("the link has no playlist ID".into()),
+      ["channel", channel_id, ..] => {
+        id(channel_id, "channel").map(|channel_id| Query::Channel(ChannelRef::Id(channel_id)))
+      }
+      ["user", username, ..] => Ok(Query::Channel(ChannelRef::Username(username.to_string()))),
+      // YouTube Music opens playlists and channels under their browse IDs
+      ["browse", browse_id] => match browse_id.strip_prefix("VL") {
+        Some(playlist_id) => id(playlist_id, "playlist").map(Query::Playlist),
+        None if browse_id.starts_with("UC") => {
+          id(browse_id, "channel").map(|channel_id| Query::Channel(ChannelRef::Id(channel_id)))
+        }
+        None => Err("only playlists and channels can be opened from YouTube Music".into()),
+      },
+      ["c", ..] => Err(
+        "custom channel links (/c/…) can't be looked up, use the channel's @handle link instead"
+          .into(),
+      ),
+      [channel_handle, ..] if channel_handle.starts_with('@') => {
+        handle(channel_handle).map(Query::Channel)
+      }
+      _ => Err("the link isn't to a playlist, channel or video".into()),
+    },
+    ("youtu.be", []) => Err("the link has no video ID".into()),
+    _ => Err(format!("{host} isn't a YouTube address")),
+  }
+}
+
+impl FromStr for Query {
+  type Err = String;
+
+  /// Parses playlist, video and channel links from youtube.com, youtu.be and
+  /// music.youtube.com, `@handle`s, and bare IDs.
+  fn from_str(input: &str) -> Result<Self, Self::Err> {
+    let input = input.trim();
+
+    if input.is_empty() {
+      return Err("enter a playlist, channel or video link".into());
+    }
+
+    if input.starts_with('@') {
+      return handle(input).map(Query::Channel);
+    }
+
+    if input.contains("://") {
+      let url = Url::parse(input).map_err(|error| format!("the link is invalid: {error}"))?;
+      return parse_url(&url);
+    }
+
+    // links are often copied without the scheme
+    if input.contains('/') || input.contains('.') {
+      let url = Url::parse(&format!("https://{input}"))
+        .map_err(|error| format!("the link is invalid: {error}"))?;
+      return parse_url(&url);
+    }
+
+    parse_id(input)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const VIDEO_ID: &str = "dQw4w9WgXcQ";
+  const CHANNEL_ID: &str = "UCuAXFkgsw1L7xaCfnd5JJOw";
+  const PLAYLIST_ID: &str = "PLFgquLnL59alCl_2TQvOiD5Vgm1hCaGSI";
+
+  fn parse(input: &str) -> Result<Query, String> {
+    input.parse()
+  }
+
+  fn video() -> Query {
+    Query::Video(VIDEO_ID.into())
+  }
+
+  fn playlist() -> Query {
+    Query::Playlist(PLAYLIST_ID.into())
+  }
+
+  fn channel() -> Query {
+    Query::Channel(ChannelRef::Id(CHANNEL_ID.into()))
+  }
+
+  #[test]
+  fn playlist_links() {
+    for input in [
+      format!("https://www.youtube.com/playlist?list={PLAYLIST_ID}"),
+      format!("https://m.youtube.com/playlist?list={PLAYLIST_ID}"),
+      format!("https://music.youtube.com/playlist?list={PLAYLIST_ID}"),
+      format!("https://music.youtube.com/browse/VL{PLAYLIST_ID}"),
+      format!("youtube.com/playlist?list={PLAYLIST_ID}"),
+    ] {
+      assert_eq!(parse(&input), Ok(playlist()), "{input}");
+    }
+  }
+
+  #[test]
+  fn playlist_takes_precedence_over_video() {
+    for input in [
+      format!("https://www.youtube.com/watch?v={VIDEO_ID}&list={PLAYLIST_ID}&index=3"),
+      format!("https://youtu.be/{VIDEO_ID}?list={PLAYLIST_ID}"),
+    ] {
+      assert_eq!(parse(&input), Ok(playlist()), "{input}");
+    }
+  }
+
+  #[test]
+  fn video_links() {
+    for input in [
+      format!("https://www.youtube.com/watch?v={VIDEO_ID}"),
+      format!("https://www.youtube.com/watch?feature=share&v={VIDEO_ID}&t=42s"),
+      format!("https://m.youtube.com/watch?v={VIDEO_ID}"),
+      format!("https://music.youtube.com/watch?v={VIDEO_ID}"),
+      format!("https://youtu.be/{VIDEO_ID}"),
+      format!("https://youtu.be/{VIDEO_ID}?t=42"),
+      format!("https://www.youtube.com/shorts/{VIDEO_ID}"),
+      format!("https://www.youtube.com/live/{VIDEO_ID}?feature=share"),
+      format!("https://www.youtube.com/embed/{VIDEO_ID}"),
+      format!("https://www.youtube-nocookie.com/embed/{VIDEO_ID}"),
+      format!("https://www.youtube.com/v/{VIDEO_ID}"),
+      format!("youtu.be/{VIDEO_ID}"),
+      format!("www.youtube.com/watch?v={VIDEO_ID}"),
+    ] {
+      assert_eq!(parse(&input), Ok(video()), "{input}");
+    }
+  }
+
+  #[test]
+  fn channel_links() {
+    for input in [
+      format!("https://www.youtube.com/channel/{CHANNEL_ID}"),
+      format!("https://www.youtube.com/channel/{CHANNEL_ID}/videos"),
+      format!("https://music.youtube.com/browse/{CHANNEL_ID}"),
+      format!("youtube.com/channel/{CHANNEL_ID}"),
+    ] {
+      assert_eq!(parse(&input), Ok(channel()), "{input}");
+    }
+
+    assert_eq!(
+      parse("https://www.youtube.com/user/someone"),
+      Ok(Query::Channel(ChannelRef::Username("someone".into())))
+    );
+  }
+
+  #[test]
+  fn handles() {
+    let handle = Ok(Query::Channel(ChannelRef::Handle("Some.Channel_1".into())));
+
+    for input in [
+      "@Some.Channel_1",
+      "  @Some.Channel_1  ",
+      "https://www.youtube.com/@Some.Channel_1",
+      "https://www.youtube.com/@Some.Channel_1/playlists",
+      "youtube.com/@Some.Channel_1",
+    ] {
+      assert_eq!(parse(input), handle, "{input}");
+    }
+  }
+
+  #[test]
+  fn bare_ids() {
+    assert_eq!(parse(VIDEO_ID), Ok(video()));
+    assert_eq!(parse(CHANNEL_ID), Ok(channel()));
+    assert_eq!(parse(PLAYLIST_ID), Ok(playlist()));
+    assert_eq!(parse(&format!(" {PLAYLIST_ID}\n")), Ok(playlist()));
+
+    // 24 characters, but not a channel
+    assert_eq!(
+      parse("PLabcdefghijklmnopqrstuv"),
+      Ok(Query::Playlist("PLabcdefghijklmnopqrstuv".into()))
+    );
+  }
+
+  #[test]
+  fn rejected_input() {
+    for input in [
+      "",
+      "   ",
+      "not an id",
+      "@",
+      "@with space",
+      "https://example.com/watch?v=dQw4w9WgXcQ",
+      "https://www.youtube.com/",
+      "https://www.youtube.com/watch",
+      "https://www.youtube.com/watch?v=not%20an%20id",
+      "https://www.youtube.com/playlist",
+      "https://www.youtube.com/playlist?list=",
+      "https://www.youtube.com/c/SomeChannel",
+      "https://www.youtube.com/feed/subscriptions",
+      "https://youtu.be/",
+      "https://music.youtube.com/browse/MPREb_abcdef",
+      "https://www.youtube.com/channel/not%20an%20id",
+      "https://",
+    ] {
+      assert!(parse(input).is_err(), "{input} was accepted");
+    }
+  }
+
+  #[test]
+  fn custom_channel_links_explain_what_to_use() {
+    let error = parse("https://www.youtube.com/c/SomeChannel").unwrap_err();
+    assert!(error.contains("@handle"), "{error}");
+  }
+
+  #[test]
+  fn playlist_ids() {
+    assert_eq!(playlist().playlist_id(), Some(PLAYLIST_ID.into()));
+    assert_eq!(
+      channel().playlist_id(),
+      Some("UUuAXFkgsw1L7xaCfnd5JJOw".into())
+    );
+    assert_eq!(
+      Query::Channel(ChannelRef::Handle("someone".into())).playlist_id(),
+      None
+    );
+    assert_eq!(video().playlist_id(), None);
+  }
+}
@@ -0,0 +1,231 @@
+use crate::{
+  format::FormatPreference,
+  library::{self, APP_DIR_NAME},
+};
+use derive_more::Display;
+use dotenvy::{dotenv, var};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+const DEFAULT_REDIRECT_PORT: u16 = 6969;
+const DEFAULT_MAX_CONCURRENT: usize = 2;
+pub const MAX_CONCURRENT_LIMIT: usize = 8;
+
+#[derive(Clone, Copy, Default, PartialEq, Display, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Theme {
+  #[default]
+  #[display(fmt = "dark")]
+  Dark,
+  #[display(fmt = "light")]
+  Light,
+}
+
+impl Theme {
+  pub fn visuals(&self) -> egui::Visuals {
+    match self {
+      Self::Dark => egui::Visuals::dark(),
+      Self::Light => egui::Visuals::light(),
+    }
+  }
+}
+
+/// How to connect to the YouTube Data API: an API key for public data, or an OAuth client
+/// to sign in with.
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct YouTubeSettings {
+  pub api_key: String,
+  pub client_id: String,
+  pub client_secret: String,
+  pub auth_uri: String,
+  pub token_uri: String,
+  /// Local port the browser is sent back to after signing in.
+  pub redirect_port: u16,
+}
+
+impl Default for YouTubeSettings {
+  fn default() -> Self {
+    Self {
+      api_key: String::new(),
+      client_id: String::new(),
+      client_secret: String::new(),
+      auth_uri: "https://accounts.google.com/o/oauth2/auth".into(),
+      token_uri: "https://oauth2.googleapis.com/token".into(),
+      redirect_port: DEFAULT_REDIRECT_PORT,
+    }
+  }
+}
+
+impl YouTubeSettings {
+  /// Fills in whatever the `.env` file or environment has, which is how credentials were
+  /// configured before there was a settings file.
+  fn import_env(&mut self) {
+    _ = dotenv();
+
+    for (field, name) in [
+      (&mut self.api_key, "API_KEY"),
+      (&mut self.client_id, "CLIENT_ID"),
+      (&mut self.client_secret, "CLIENT_SECRET"),
+      (&mut self.auth_uri, "AUTH_URI"),
+      (&mut self.token_uri, "TOKEN_URI"),
+    ] {
+      if let Ok(value) = var(name) {
+        *field = value;
+      }
+    }
+  }
+
+  pub fn has_oauth_client(&self) -> bool {
+    !self.client_id.is_empty() && !self.client_secret.is_empty()
+  }
+}
+
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DownloadSettings {
+  /// What the download buttons and the command line download when no format is picked.
+  pub default_format: FormatPreference,
+  /// How many downloads run at once.
+  pub max_concurrent: usize,
+}
+
+impl Default for DownloadSettings {
+  fn default() -> Self {
+    Self {
+      default_format: FormatPreference::default(),
+      max_concurrent: DEFAULT_MAX_CONCURRENT,
+    }
+  }
+}
+
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+  pub library_dir: PathBuf,
+  pub theme: Theme,
+  pub downloads: DownloadSettings,
+  pub youtube: YouTubeSettings,
+}
+
+impl Default for Settings {
+  fn default() -> Self {
+    Self {
+      library_dir: library::default_dir(),
+      theme: Theme::default(),
+      downloads: DownloadSettings::default(),
+      youtube: YouTubeSettings::default(),
+    }
+  }
+}
+
+/// Per-user directory for the settings and anything else that isn't part of the library.
+pub fn config_dir() -> Option<PathBuf> {
+  dirs::config_dir().map(|dir| dir.join(APP_DIR_NAME))
+}
+
+fn settings_path() -> Option<PathBuf> {
+  config_dir().map(|dir| dir.join("settings.toml"))
+}
+
+impl Settings {
+  /// Reads the settings file, creating it on first launch.
+  ///
+  /// When the file can't be read, the defaults are returned along with an explanation, and the
+  /// file is left as it is until the settings are saved.
+  pub fn load() -> (Self, Option<String>) {
+    let Some(path) = settings_path() else {
+      return (Self::default(), None);
+    };
+
+    match std::fs::read_to_string(&path) {
+      Ok(contents) => match toml::from_str(&contents) {
+        Ok(settings) => (settings, None),
+        Err(error) => (
+          Self::default(),
+          Some(format!("{} is invalid: {error}", path.display())),
+        ),
+      },
+      Err(error) if error.kind() == std::io::ErrorKind::NotFound => (Self::create(), None),
+      Err(error) => (
+        Self::default(),
+        Some(format!("{} can't be read: {error}", path.display())),
+      ),
+    }
+  }
+
+  fn create() -> Self {
+    let mut settings = Self::default();
+    settings.youtube.import_env();
+
+    // first launch: bring over anything downloaded into the old compile-time location
+    _ = library::migrate(&library::legacy_dir(), &settings.library_dir);
+
+    settings.save();
+
+    settings
+  }
+
+  pub fn save(&self) {
+    let Some(path) = settings_path() else {
+      return;
+    };
+
+    if let Some(parent) = path.parent() {
+      _ = std::fs::create_dir_all(parent);
+    }
+
+    if let Ok(contents) = toml::to_string_pretty(self) {
+      _ = std::fs::write(path, contents);
+    }
+  }
+
+  /// Everything that is wrong with the settings, phrased so it can be shown as is.
+  pub fn problems(&self) -> Vec<String> {
+    let mut problems = Vec::new();
+    let youtube = &self.youtube;
+
+    if !self.library_dir.is_absolute() {
+      problems.push("the library folder has to be a full path".into());
+    }
+
+    if !(1..=MAX_CONCURRENT_LIMIT).contains(&self.downloads.max_concurrent) {
+      problems.push(format!(
+        "downloads at once has to be between 1 and {MAX_CONCURRENT_LIMIT}"
+      ));
+    }
+
+    if !youtube.api_key.is_empty()
+      && (!youtube.api_key.starts_with("AIza") || youtube.api_key.len() != 39)
+    {
+      problems.push(
+        "the API key doesn't look like one from the Google Cloud console (AIza…, 39 characters)"
+          .into(),
+      );
+    }
+
+    if youtube.client_id.is_empty() != youtube.client_secret.is_empty() {
+      problems.push("signing in needs both the client ID and the client secret".into());
+    }
+
+    if !youtube.client_id.is_empty() && !youtube.client_id.ends_with(".apps.googleusercontent.com")
+    {
+      problems.push("the client ID should end in .apps.googleusercontent.com".into());
+    }
+
+    for (name, uri) in [
+      ("auth URI", &youtube.auth_uri),
+      ("token URI", &youtube.token_uri),
+    ] {
+      if !uri.starts_with("https://") {
+        problems.push(format!("the {name} has to be an https:// address"));
+      }
+    }
+
+    if youtube.redirect_port < 1024 {
+      problems.push("the redirect port has to be 1024 or above".into());
+    }
+
+    problems
+  }
+}
@@ -0,0 +1,928 @@
+use crate::{
+  error::Error,
+  query::{ChannelRef, Query},
+  settings::{self, YouTubeSettings},
+};
+use derive_more::{Deref, Display};
+use google_apis_common::NoToken;
+use google_youtube3::{
+  api::{
+    ChannelSnippet, PlaylistItem, PlaylistItemListResponse, PlaylistItemSnippet, PlaylistSnippet,
+    Scope, SearchResultSnippet, Thumbnail as ApiThumbnail, ThumbnailDetails, Video, VideoSnippet,
+  },
+  hyper::{self, client::HttpConnector},
+  hyper_rustls::{self, HttpsConnector},
+  oauth2::{ApplicationSecret, InstalledFlowAuthenticator, InstalledFlowReturnMethod},
+  YouTube,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+  collections::HashMap,
+  path::{Path, PathBuf},
+  sync::Arc,
+};
+
+const REVOKE_URI: &str = "https://oauth2.googleapis.com/revoke";
+
+#[derive(Deref)]
+pub struct YouTubeClient {
+  #[deref]
+  hub: YouTube<HttpsConnector<HttpConnector>>,
+  /// Sent with every request instead of an OAuth token, which is enough for public data.
+  api_key: Option<String>,
+}
+
+impl YouTubeClient {
+  /// Whether requests are made on behalf of a signed in account rather than with an API key.
+  pub fn uses_account(&self) -> bool {
+    self.api_key.is_none()
+  }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct Thumbnail {
+  url: String,
+  width: u32,
+}
+
+/// Every size YouTube offers an image in, smallest first.
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct Thumbnails(Vec<Thumbnail>);
+
+impl Thumbnails {
+  fn from_api(thumbnails: Option<ThumbnailDetails>) -> Self {
+    let Some(ThumbnailDetails {
+      default,
+      medium,
+      high,
+      standard,
+      maxres,
+      ..
+    }) = thumbnails
+    else {
+      return Self::default();
+    };
+
+    // the widths YouTube uses when a size doesn't say
+    let mut thumbnails = [
+      (default, 120),
+      (medium, 320),
+      (high, 480),
+      (standard, 640),
+      (maxres, 1280),
+    ]
+    .into_iter()
+    .filter_map(|(thumbnail, usual_width)| {
+      let ApiThumbnail { url, width, .. } = thumbnail?;
+
+      Some(Thumbnail {
+        url: url?,
+        width: width.unwrap_or(usual_width),
+      })
+    })
+    .collect::<Vec<_>>();
+    thumbnails.sort_by_key(|thumbnail| thumbnail.width);
+
+    Self(thumbnails)
+  }
+
+  /// The smallest image at least `width` pixels wide, or the largest there is.
+  pub fn best(&self, width: f32) -> Option<&str> {
+    self
+      .0
+      .iter()
+      .find(|thumbnail| thumbnail.width as f32 >= width)
+      .or_else(|| self.0.last())
+      .map(|thumbnail| thumbnail.url.as_str())
+  }
+
+  pub fn urls(&self) -> impl Iterator<Item = &str> {
+    self.0.iter().map(|thumbnail| thumbnail.url.as_str())
+  }
+
+  /// The smallest image, or empty when YouTube has none.
+  pub fn smallest_url(&self) -> String {
+    self.urls().next().unwrap_or_default().to_string()
+  }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct YouTubeChannel {
+  pub id: String,
+  pub name: String,
+  pub avatar_url: String,
+  pub avatars: Thumbnails,
+  /// Unknown for channels that come from search results.
+  pub uploads_playlist_id: Option<String>,
+}
+
+impl YouTubeChannel {
+  /// The avatar that looks sharp `width` pixels wide.
+  pub fn avatar(&self, width: f32) -> &str {
+    self.avatars.best(width).unwrap_or(&self.avatar_url)
+  }
+}
+
+#[derive(Serialize)]
+pub struct PlaylistInfo {
+  pub id: String,
+  pub title: String,
+  pub channel: YouTubeChannel,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct PlaylistVideo {
+  pub id: String,
+  pub title: String,
+  pub thumbnail_url: String,
+  pub thumbnails: Thumbnails,
+  /// Looked up separately from the playlist, so missing when that failed.
+  pub details: Option<VideoDetails>,
+}
+
+impl PlaylistVideo {
+  /// The thumbnail that looks sharp `width` pixels wide.
+  pub fn thumbnail(&self, width: f32) -> &str {
+    self.thumbnails.best(width).unwrap_or(&self.thumbnail_url)
+  }
+}
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct VideoDetails {
+  /// In seconds; missing for upcoming live streams.
+  pub duration: Option<u64>,
+  /// As `YYYY-MM-DD`.
+  pub published: Option<String>,
+  pub view_count: Option<u64>,
+  /// Missing when the channel hides likes.
+  pub like_count: Option<u64>,
+  pub channel_name: Option<String>,
+  pub description: String,
+}
+
+pub struct PlaylistVideos {
+  pub videos: Vec<PlaylistVideo>,
+  pub next_cursor: Option<String>,
+  pub total_count: Option<u32>,
+  /// Why some of the videos are without details, if looking them up failed.
+  pub details_error: Option<Error>,
+}
+
+#[derive(Clone)]
+pub struct ChannelPlaylist {
+  pub id: String,
+  pub title: String,
+  pub thumbnails: Thumbnails,
+  pub video_count: Option<u32>,
+}
+
+pub struct ChannelPlaylists {
+  pub playlists: Vec<ChannelPlaylist>,
+  pub next_cursor: Option<String>,
+}
+
+/// What to search YouTube for.
+#[derive(Clone, Copy, Default, PartialEq, Display)]
+pub enum SearchKind {
+  #[default]
+  #[display(fmt = "videos")]
+  Video,
+  #[display(fmt = "playlists")]
+  Playlist,
+  #[display(fmt = "channels")]
+  Channel,
+}
+
+impl SearchKind {
+  fn api_type(&self) -> &'static str {
+    match self {
+      Self::Video => "video",
+      Self::Playlist => "playlist",
+      Self::Channel => "channel",
+    }
+  }
+}
+
+#[derive(Clone)]
+pub struct SearchResult {
+  /// Opens the result: a video, a playlist, or a channel by its ID.
+  pub query: Query,
+  pub title: String,
+  pub channel_name: String,
+  pub thumbnails: Thumbnails,
+}
+
+pub struct SearchResults {
+  pub results: Vec<SearchResult>,
+  pub next_cursor: Option<String>,
+}
+
+/// What a query turned out to be.
+pub enum Listing {
+  /// Its videos still have to be fetched, page by page.
+  Playlist(PlaylistInfo),
+  /// A single video, listed on its own under its title.
+  Video(PlaylistInfo, PlaylistVideos),
+}
+
+fn token_path() -> Option<PathBuf> {
+  settings::config_dir().map(|dir| dir.join("tokens.json"))
+}
+
+/// Keeps the stored tokens readable by the current user only. Elsewhere than on unix, the
+/// per-user config directory they are in is already private to the user.
+fn restrict_token_permissions(token_path: &Path) {
+  #[cfg(unix)]
+  {
+    use std::{fs::Permissions, os::unix::fs::PermissionsExt};
+
+    if let Some(parent) = token_path.parent() {
+      _ = std::fs::create_dir_all(parent);
+      _ = std::fs::set_permissions(parent, Permissions::from_mode(0o700));
+    }
+
+    if token_path.exists() {
+      _ = std::fs::set_permissions(token_path, Permissions::from_mode(0o600));
+    }
+  }
+}
+
+/// The first `key` string anywhere in `value`; how tokens are stored is up to yup-oauth2.
+fn find_string<'a>(value: &'a Value, key: &str) -> Option<&'a str> {
+  match value {
+    Value::Object(map) => map
+      .get(key)
+      .and_then(Value::as_str)
+      .or_else(|| map.values().find_map(|value| find_string(value, key))),
+    Value::Array(values) => values.iter().find_map(|value| find_string(value, key)),
+    _ => None,
+  }
+}
+
+/// Revokes the stored grant and deletes the stored tokens, so the next connection asks for
+/// consent again and can pick another account.
+pub async fn sign_out() {
+  let Some(token_path) = token_path() else {
+    return;
+  };
+
+  let stored_tokens = std::fs::read(&token_path)
+    .ok()
+    .and_then(|bytes| serde_json::from_slice::<Value>(&bytes).ok());
+
+  // revoking the refresh token also revokes the access tokens issued from it
+  let token = stored_tokens.as_ref().and_then(|stored_tokens| {
+    find_string(stored_tokens, "refresh_token")
+      .or_else(|| find_string(stored_tokens, "access_token"))
+  });
+
+  // offline, the tokens are still deleted so nobody can use them from this machine
+  if let Some(token) = token {
+    _ = reqwest::Client::new()
+      .post(REVOKE_URI)
+      .form(&[("token", token)])
+      .send()
+      .await;
+  }
+
+  _ = std::fs::remove_file(token_path);
+}
+
+/// Connects with the API key when one is configured, since reading public playlists doesn't
+/// need the user's consent, and otherwise signs in through the browser, reusing the tokens
+/// from the last sign in if they are still valid or can be refreshed.
+pub async fn fetch_youtube_client(settings: YouTubeSettings) -> Result<YouTubeClient, Error> {
+  let connector = hyper_rustls::HttpsConnectorBuilder::new()
+    .with_native_roots()
+    .map_err(|error| Error::Network(format!("no TLS root certificates: {error}")))?
+    .https_or_http()
+    .enable_http1()
+    .build();
+
+  if !settings.api_key.is_empty() {
+    return Ok(YouTubeClient {
+      hub: YouTube::new(hyper::Client::builder().build(connector), NoToken),
+      api_key: Some(settings.api_key),
+    });
+  }
+
+  if !settings.has_oauth_client() {
+    return Err(Error::Auth(
+      "no API key or OAuth client configured, add one in the settings to connect to YouTube".into(),
+    ));
+  }
+
+  let secret = ApplicationSecret {
+    client_id: settings.client_id,
+    client_secret: settings.client_secret,
+    auth_uri: settings.auth_uri,
+    token_uri: settings.token_uri,
+    redirect_uris: vec![format!("http://localhost:{}", settings.redirect_port)],
+    project_id: None,
+    client_email: None,
+    auth_provider_x509_cert_url: None,
+    client_x509_cert_url: None,
+  };
+
+  let mut auth_builder = InstalledFlowAuthenticator::builder(
+    secret,
+    InstalledFlowReturnMethod::HTTPPortRedirect(settings.redirect_port),
+  );
+
+  let token_path = token_path();
+
+  if let Some(token_path) = &token_path {
+    restrict_token_permissions(token_path);
+    auth_builder = auth_builder.persist_tokens_to_disk(token_path);
+  }
+
+  let auth = auth_builder
+    .build()
+    .await
+    .map_err(|error| Error::Auth(error.to_string()))?;
+
+  // sign in now rather than on the first request, so a refused consent is reported as such
+  auth
+    .token(&[Scope::Readonly.as_ref()])
+    .await
+    .map_err(|error| Error::Auth(error.to_string()))?;
+
+  // the first sign-in only just created the file
+  if let Some(token_path) = &token_path {
+    restrict_token_permissions(token_path);
+  }
+
+  Ok(YouTubeClient {
+    hub: YouTube::new(hyper::Client::builder().build(connector), auth),
+    api_key: None,
+  })
+}
+
+async fn fetch_channel(
+  yt_client: Arc<YouTubeClient>,
+  user_id: &str,
+) -> Result<YouTubeChannel, Error> {
+  let mut channels_query = yt_client
+    .channels()
+    .list(&vec!["snippet".into(), "contentDetails".into()])
+    .add_id(user_id);
+
+  if let Some(api_key) = &yt_client.api_key {
+    channels_query = channels_query.param("key", api_key);
+  }
+
+  let what = || format!("channel {user_id}");
+
+  let (_, channels) = channels_query
+    .doit()
+    .await
+    .map_err(|error| Error::from_api(error, what()))?;
+
+  let channel = channels
+    .items
+    .and_then(|channels| channels.into_iter().next())
+    .ok_or_else(|| Error::not_found(what()))?;
+
+  let uploads_playlist_id = channel
+    .content_details
+    .and_then(|content_details| content_details.related_playlists?.uploads);
+
+  let ChannelSnippet {
+    title, thumbnails, ..
+  } = channel
+    .snippet
+    .ok_or_else(|| Error::missing("channel snippet"))?;
+
+  let avatars = Thumbnails::from_api(thumbnails);
+
+  Ok(YouTubeChannel {
+    id: user_id.to_string(),
+    name: title.ok_or_else(|| Error::missing("channel name"))?,
+    avatar_url: avatars.smallest_url(),
+    avatars,
+    uploads_playlist_id,
+  })
+}
+
+async fn fetch_channel_playlists_page(
+  yt_client: Arc<YouTubeClient>,
+  channel_id: &str,
+  cursor: Option<String>,
+) -> Result<ChannelPlaylists, Error> {
+  let mut playlists_query = yt_client
+    .playlists()
+    .list(&vec!["snippet".into(), "contentDetails".into()])
+    .channel_id(channel_id)
+    .max_results(50);
+
+  if let Some(cursor) = cursor {
+    playlists_query = playlists_query.page_token(&cursor);
+  }
+
+  if let Some(api_key) = &yt_client.api_key {
+    playlists_query = playlists_query.param("key", api_key);
+  }
+
+  let (_, playlists) = playlists_query
+    .doit()
+    .await
+    .map_err(|error| Error::from_api(error, format!("channel {channel_id}")))?;
+
+  Ok(ChannelPlaylists {
+    playlists: playlists
+      .items
+      .unwrap_or_default()
+      .into_iter()
+      .filter_map(|playlist| {
+        let PlaylistSnippet {
+          title, thumbnails, ..
+        } = playlist.snippet?;
+
+        Some(ChannelPlaylist {
+          id: playlist.id?,
+          title: title?,
+          thumbnails: Thumbnails::from_api(thumbnails),
+          video_count: playlist
+            .content_details
+            .and_then(|content_details| content_details.item_count),
+        })
+      })
+      .collect(),
+    next_cursor: playlists.next_page_token,
+  })
+}
+
+/// Fetches every playlist the channel owns, handing each page to `on_page` as it arrives.
+pub async fn fetch_all_channel_playlists(
+  yt_client: Arc<YouTubeClient>,
+  channel_id: &str,
+  mut on_page: impl FnMut(ChannelPlaylists),
+) -> Result<(), Error> {
+  let mut cursor = None;
+
+  loop {
+    let channel_playlists =
+      fetch_channel_playlists_page(yt_client.clone(), channel_id, cursor).await?;
+
+    cursor = channel_playlists.next_cursor.clone();
+    let is_last_page = cursor.is_none();
+
+    on_page(channel_playlists);
+
+    if is_last_page {
+      return Ok(());
+    }
+  }
+}
+
+/// Looks up the playlist a channel's uploads are in.
+async fn fetch_uploads_playlist_id(
+  yt_client: Arc<YouTubeClient>,
+  channel: &ChannelRef,
+) -> Result<String, Error> {
+  if let Some(playlist_id) = channel.uploads_playlist_id() {
+    return Ok(playlist_id);
+  }
+
+  let mut channels_query = yt_client.channels().list(&vec!["contentDetails".into()]);
+
+  let what = match channel {
+    ChannelRef::Id(id) => {
+      channels_query = channels_query.add_id(id);
+      format!("channel {id}")
+    }
+    ChannelRef::Handle(handle) => {
+      channels_query = channels_query.for_handle(handle);
+      format!("channel @{handle}")
+    }
+    ChannelRef::Username(username) => {
+      channels_query = channels_query.for_username(username);
+      format!("user {username}")
+    }
+  };
+
+  if let Some(api_key) = &yt_client.api_key {
+    channels_query = channels_query.param("key", api_key);
+  }
+
+  let (_, channels) = channels_query
+    .doit()
+    .await
+    .map_err(|error| Error::from_api(error, what.clone()))?;
+
+  channels
+    .items
+    .and_then(|channels| channels.into_iter().next())
+    .ok_or_else(|| Error::not_found(what))?
+    .content_details
+    .and_then(|content_details| content_details.related_playlists?.uploads)
+    .ok_or_else(|| Error::missing("uploads playlist"))
+}
+
+async fn fetch_video(yt_client: Arc<YouTubeClient>, video_id: &str) -> Result<Listing, Error> {
+  let what = || format!("video {video_id}");
+
+  let video = query_videos(&yt_client, &[video_id], &what())
+    .await?
+    .into_iter()
+    .next()
+    .ok_or_else(|| Error::not_found(what()))?;
+
+  let details = video_details(&video);
+
+  let VideoSnippet {
+    channel_id,
+    title,
+    thumbnails,
+    ..
+  } = video
+    .snippet
+    .ok_or_else(|| Error::missing("video snippet"))?;
+
+  let channel_id = channel_id.ok_or_else(|| Error::missing("video channel"))?;
+  let title = title.ok_or_else(|| Error::missing("video title"))?;
+
+  let thumbnails = Thumbnails::from_api(thumbnails);
+
+  let video = PlaylistVideo {
+    id: video_id.to_string(),
+    title: title.clone(),
+    thumbnail_url: thumbnails.smallest_url(),
+    thumbnails,
+    details: Some(details),
+  };
+
+  Ok(Listing::Video(
+    PlaylistInfo {
+      id: video_id.to_string(),
+      title,
+      channel: fetch_channel(yt_client, &channel_id).await?,
+    },
+    PlaylistVideos {
+      videos: vec![video],
+      next_cursor: None,
+      total_count: Some(1),
+      details_error: None,
+    },
+  ))
+}
+
+/// Looks up at most 50 videos by ID, the most a single request can ask for.
+async fn query_videos(
+  yt_client: &YouTubeClient,
+  video_ids: &[&str],
+  what: &str,
+) -> Result<Vec<Video>, Error> {
+  let mut videos_query = yt_client.videos().list(&vec![
+    "snippet".into(),
+    "contentDetails".into(),
+    "statistics".into(),
+  ]);
+
+  for video_id in video_ids {
+    videos_query = videos_query.add_id(video_id);
+  }
+
+  if let Some(api_key) = &yt_client.api_key {
+    videos_query = videos_query.param("key", api_key);
+  }
+
+  let (_, videos) = videos_query
+    .doit()
+    .await
+    .map_err(|error| Error::from_api(error, what))?;
+
+  Ok(videos.items.unwrap_or_default())
+}
+
+/// Parses an ISO 8601 duration such as `PT1H2M3S` or `P1DT2H` into seconds.
+fn parse_duration(duration: &str) -> Option<u64> {
+  let mut seconds = 0;
+  let mut number = String::new();
+
+  for c in duration.strip_prefix('P')?.chars() {
+    let unit = match c {
+      '0'..='9' => {
+        number.push(c);
+        continue;
+      }
+      'T' => continue,
+      'W' => 7 * 24 * 60 * 60,
+      'D' => 24 * 60 * 60,
+      'H' => 60 * 60,
+      'M' => 60,
+      'S' => 1,
+      _ => return None,
+    };
+
+    seconds += number.parse::<u64>().ok()? * unit;
+    number.clear();
+  }
+
+  // a number without a unit after it
+  number.is_empty().then_some(seconds)
+}
+
+fn video_details(video: &Video) -> VideoDetails {
+  let snippet = video.snippet.as_ref();
+  let statistics = video.statistics.as_ref();
+
+  VideoDetails {
+    // live streams that haven't started yet last `P0D`
+    duration: video
+      .content_details
+      .as_ref()
+      .and_then(|content_details| content_details.duration.as_deref())
+      .and_then(parse_duration)
+      .filter(|duration| *duration > 0),
+    published: snippet
+      .and_then(|snippet| snippet.published_at)
+      .map(|published_at| published_at.date_naive().to_string()),
+    view_count: statistics.and_then(|statistics| statistics.view_count),
+    like_count: statistics.and_then(|statistics| statistics.like_count),
+    channel_name: snippet.and_then(|snippet| snippet.channel_title.clone()),
+    description: snippet
+      .and_then(|snippet| snippet.description.clone())
+      .unwrap_or_default(),
+  }
+}
+
+/// Fills in the details of `videos` with one request per 50 videos. The videos of a request
+/// that fails are left without details rather than failing the page they are on, and the
+/// last such failure is returned.
+async fn fetch_video_details(
+  yt_client: &YouTubeClient,
+  videos: &mut [PlaylistVideo],
+) -> Option<Error> {
+  let mut failure = None;
+
+  for batch in videos.chunks_mut(50) {
+    let video_ids = batch
+      .iter()
+      .map(|video| video.id.as_str())
+      .collect::<Vec<_>>();
+
+    let videos = match query_videos(yt_client, &video_ids, "videos").await {
+      Ok(videos) => videos,
+      Err(error) => {
+        failure = Some(error);
+        continue;
+      }
+    };
+
+    let mut details = videos
+      .into_iter()
+      .filter_map(|video| Some((video.id.clone()?, video_details(&video))))
+      .collect::<HashMap<_, _>>();
+
+    // deleted and private videos are left without details
+    for video in batch.iter_mut() {
+      video.details = details.remove(&video.id);
+    }
+  }
+
+  failure
+}
+
+/// Finds out what `query` refers to, fetching the playlist's details or the single video.
+pub async fn fetch_listing(yt_client: Arc<YouTubeClient>, query: &Query) -> Result<Listing, Error> {
+  let playlist_id = match query {
+    Query::Playlist(playlist_id) => playlist_id.clone(),
+    Query::Channel(channel) => fetch_uploads_playlist_id(yt_client.clone(), channel).await?,
+    Query::Video(video_id) => return fetch_video(yt_client, video_id).await,
+  };
+
+  fetch_playlist_info(yt_client, &playlist_id)
+    .await
+    .map(Listing::Playlist)
+}
+
+async fn fetch_playlist_info(
+  yt_client: Arc<YouTubeClient>,
+  playlist_id: &str,
+) -> Result<PlaylistInfo, Error> {
+  let mut playlists_query = yt_client
+    .playlists()
+    .list(&vec!["snippet".into()])
+    .add_id(playlist_id);
+
+  if let Some(api_key) = &yt_client.api_key {
+    playlists_query = playlists_query.param("key", api_key);
+  }
+
+  let what = || format!("playlist {playlist_id}");
+
+  let (_, playlists) = playlists_query
+    .doit()
+    .await
+    .map_err(|error| Error::from_api(error, what()))?;
+
+  let PlaylistSnippet {
+    channel_id, title, ..
+  } = playlists
+    .items
+    .and_then(|playlists| playlists.into_iter().next())
+    .ok_or_else(|| Error::not_found(what()))?
+    .snippet
+    .ok_or_else(|| Error::missing("playlist snippet"))?;
+
+  let channel_id = channel_id.ok_or_else(|| Error::missing("playlist channel"))?;
+
+  Ok(PlaylistInfo {
+    id: playlist_id.to_string(),
+    title: title.ok_or_else(|| Error::missing("playlist title"))?,
+    channel: fetch_channel(yt_client, &channel_id).await?,
+  })
+}
+
+pub async fn fetch_video_page_with_cursor(
+  yt_client: Arc<YouTubeClient>,
+  playlist_id: &str,
+  cursor: Option<String>,
+) -> Result<PlaylistVideos, Error> {
+  let mut videos_query = yt_client
+    .playlist_items()
+    .list(&vec!["snippet".into(), "contentDetails".into()])
+    .playlist_id(playlist_id)
+    .max_results(50);
+
+  if let Some(cursor) = cursor {
+    videos_query = videos_query.page_token(&cursor);
+  }
+
+  if let Some(api_key) = &yt_client.api_key {
+    videos_query = videos_query.param("key", api_key);
+  }
+
+  let (_, videos) = videos_query
+    .doit()
+    .await
+    .map_err(|error| Error::from_api(error, format!("playlist {playlist_id}")))?;
+
+  let PlaylistItemListResponse {
+    items: videos,
+    next_page_token: next_cursor,
+    page_info,
+    ..
+  } = videos;
+
+  let mut videos = videos
+    .unwrap_or_default()
+    .into_iter()
+    .filter_map(
+      |PlaylistItem {
+         snippet,
+         content_details,
+         ..
+       }| {
+        let PlaylistItemSnippet {
+          title, thumbnails, ..
+        } = snippet?;
+
+        let thumbnails = Thumbnails::from_api(thumbnails);
+
+        Some(PlaylistVideo {
+          id: content_details?.video_id?,
+          title: title?,
+          thumbnail_url: thumbnails.smallest_url(),
+          thumbnails,
+          details: None,
+        })
+      },
+    )
+    .collect::<Vec<_>>();
+
+  let details_error = fetch_video_details(&yt_client, &mut videos).await;
+
+  Ok(PlaylistVideos {
+    videos,
+    details_error,
+    next_cursor,
+    total_count: page_info
+      .and_then(|page_info| page_info.total_results)
+      .and_then(|total_results| u32::try_from(total_results).ok()),
+  })
+}
+
+/// Fetches every page of the playlist in order, handing each one to `on_page` as it arrives.
+pub async fn fetch_all_video_pages(
+  yt_client: Arc<YouTubeClient>,
+  playlist_id: &str,
+  mut on_page: impl FnMut(PlaylistVideos),
+) -> Result<(), Error> {
+  let mut cursor = None;
+
+  loop {
+    let playlist_videos_info =
+      fetch_video_page_with_cursor(yt_client.clone(), playlist_id, cursor).await?;
+
+    cursor = playlist_videos_info.next_cursor.clone();
+    let is_last_page = cursor.is_none();
+
+    on_page(playlist_videos_info);
+
+    if is_last_page {
+      return Ok(());
+    }
+  }
+}
+
+/// Search snippets come HTML-escaped, unlike everywhere else in the API.
+fn unescape_html(text: &str) -> String {
+  text
+    .replace("&quot;", "\"")
+    .replace("&#39;", "'")
+    .replace("&lt;", "<")
+    .replace("&gt;", ">")
+    .replace("&amp;", "&")
+}
+
+/// Fetches a page of search results for `terms`, of one kind only. Each page costs 100 units of
+/// the API quota, so pages are only fetched when asked for.
+pub async fn search(
+  yt_client: Arc<YouTubeClient>,
+  terms: &str,
+  kind: SearchKind,
+  cursor: Option<String>,
+) -> Result<SearchResults, Error> {
+  let mut search_query = yt_client
+    .search()
+    .list(&vec!["snippet".into()])
+    .q(terms)
+    .add_type(kind.api_type())
+    .max_results(25);
+
+  if let Some(cursor) = cursor {
+    search_query = search_query.page_token(&cursor);
+  }
+
+  if let Some(api_key) = &yt_client.api_key {
+    search_query = search_query.param("key", api_key);
+  }
+
+  let (_, results) = search_query
+    .doit()
+    .await
+    .map_err(|error| Error::from_api(error, format!("\"{terms}\"")))?;
+
+  Ok(SearchResults {
+    results: results
+      .items
+      .unwrap_or_default()
+      .into_iter()
+      .filter_map(|result| {
+        let id = result.id?;
+        let query = match kind {
+          SearchKind::Video => Query::Video(id.video_id?),
+          SearchKind::Playlist => Query::Playlist(id.playlist_id?),
+          SearchKind::Channel => Query::Channel(ChannelRef::Id(id.channel_id?)),
+        };
+
+        let SearchResultSnippet {
+          title,
+          channel_title,
+          thumbnails,
+          ..
+        } = result.snippet?;
+
+        Some(SearchResult {
+          query,
+          title: unescape_html(&title?),
+          channel_name: unescape_html(&channel_title.unwrap_or_default()),
+          thumbnails: Thumbnails::from_api(thumbnails),
+        })
+      })
+      .collect(),
+    next_cursor: results.next_page_token,
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn durations() {
+    for (duration, seconds) in [
+      ("PT45S", 45),
+      ("PT3M", 180),
+      ("PT1H2M3S", 3723),
+      ("PT10H0M1S", 36001),
+      ("P1DT2H", 93600),
+      ("P1W", 604800),
+      // upcoming live streams
+      ("P0D", 0),
+      ("PT0S", 0),
+    ] {
+      assert_eq!(parse_duration(duration), Some(seconds), "{duration}");
+    }
+  }
+
+  #[test]
+  fn invalid_durations() {
+    for duration in ["", "45S", "1H2M", "PT1.5S", "PTxS", "PT5", "PT1H2"] {
+      assert_eq!(parse_duration(duration), None, "{duration}");
+    }
+  }
+}